@@ -70,7 +70,9 @@ async fn main() -> Result<()> {
     ).await?;
     
     // Create RLS connection
-    let rls_conn = RlsConnection::new_initialized(conn).await?;
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+    // The REPL manages policies, which requires an admin connection
+    rls_conn.set_admin(true);
     
     // Create a default policy for tenant isolation (to demonstrate RLS)
     rls_conn.execute(
@@ -113,12 +115,14 @@ async fn main() -> Result<()> {
     println!("Enter SQL queries to execute with RLS applied");
     println!("\nSpecial commands:");
     println!("- CREATE POLICY <name> ON <table> USING (<expression>)");
+    println!("- ALTER POLICY <name> ON <table> USING (<expression>)");
+    println!("- DROP POLICY <name> ON <table>");
     println!("- SHOW POLICIES");
     println!("- RESET POLICIES <table>");
     println!("- exit/quit - Exit the REPL");
     println!("\nDemo Flow:");
     println!("1. Try 'SELECT * FROM users;' (note tenant_id = 100 filter applied)");
-    println!("2. Add a new policy: 'CREATE POLICY user_filter ON users USING (id = 1)'");
+    println!("2. Add a new policy: 'CREATE POLICY user_filter ON users AS RESTRICTIVE USING (id = 1)'");
     println!("3. Try 'SELECT * FROM users;' again (notice only user with id = 1 is shown)");
     
     let mut input = String::new();
@@ -139,14 +143,15 @@ async fn main() -> Result<()> {
         }
         
         // Handle special commands
-        if input.to_uppercase().starts_with("CREATE POLICY") {
-            // This is a policy creation statement, pass it directly to execute
+        let upper_input = input.to_uppercase();
+        if ["CREATE POLICY", "ALTER POLICY", "DROP POLICY"].iter().any(|prefix| upper_input.starts_with(prefix)) {
+            // This is a policy statement, pass it directly to execute
             match rls_conn.execute(input, params![]).await {
                 Ok(rows_affected) => {
-                    println!("Policy created successfully ({} rows affected)", rows_affected);
+                    println!("Policy statement executed successfully ({} rows affected)", rows_affected);
                 },
                 Err(e) => {
-                    println!("Error creating policy: {}", e);
+                    println!("Error executing policy statement: {}", e);
                 }
             }
             continue;
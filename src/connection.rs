@@ -1,32 +1,71 @@
-use crate::{policy::Policy, sql_parser, Result};
-use libsql::{Connection, params, Rows};
-use libsql::params::IntoParams;
-use regex::Regex;
-use lazy_static::lazy_static;
-use sqlparser::ast::Statement;
-
-lazy_static! {
-    // Basic regex pattern for CREATE POLICY statements
-    // This captures:
-    // 1. Policy name
-    // 2. Table name (including schema if present)
-    // 3. Optional command (SELECT, INSERT, etc.)
-    // 4. Optional USING expression
-    // 5. Optional WITH CHECK expression
-    static ref CREATE_POLICY_REGEX: Regex = Regex::new(
-        r"(?i)CREATE\s+POLICY\s+(\w+)\s+ON\s+([\w\.]+)(?:\s+FOR\s+(\w+))?(?:\s+USING\s+\(([^)]*)\))?(?:\s+WITH\s+CHECK\s+\(([^)]*)\))?").unwrap();
-}
+use crate::{
+    policy::{Policy, PolicyManager},
+    policy_check::{self, PolicyCheck, TableColumn},
+    privilege::{self, Access},
+    rls_statement::{self, AlterPolicyChange, RlsStatement},
+    role::{self, PUBLIC_ROLE},
+    session::{SessionContext, ROLE_KEY, USER_ID_KEY},
+    sql_parser::{self, MaskedColumn, ReadableColumns, RewriteStrategy},
+    Error, Result,
+};
+use libsql::{Connection, params, Rows, Value};
+use libsql::params::{IntoParams, Params};
+use sqlparser::ast::{
+    ConflictTarget, Ident, OnConflict, OnConflictAction, OnInsert, Query, SqliteOnConflict,
+    Statement,
+};
+use std::collections::HashMap;
+use std::sync::{PoisonError, RwLock};
 
 /// A wrapper around a libSQL connection that adds RLS functionality
 /// 
 /// This connection wrapper intercepts SQL statements and provides row-level security
 /// capabilities by:
 /// 
-/// 1. Recognizing and processing CREATE POLICY statements
-/// 2. Storing policy information in the _rls_policies table
+/// 1. Recognizing and processing CREATE, ALTER and DROP POLICY and
+///    `ALTER TABLE ... ROW LEVEL SECURITY` statements
+/// 2. Storing policy information in the _rls_policies and _rls_tables tables
 /// 3. Rewriting SELECT statements to apply RLS policies
+/// 4. Resolving session functions such as `current_user_id()` from the
+///    connection's session context
+/// 
+/// Non-admin connections are strict by default: statements the RLS layer
+/// can't parse or doesn't know how to protect are rejected rather than
+/// passed through.
 pub struct RlsConnection {
     conn: Connection,
+    context: RwLock<SessionContext>,
+    strategy: RewriteStrategy,
+    admin: bool,
+    /// Overrides whether the connection is strict, which otherwise depends
+    /// on `admin`
+    strict: Option<bool>,
+    /// Set for connections that skip row level security altogether
+    bypass: Option<BypassToken>,
+}
+
+/// A capability allowing a connection to bypass row level security
+///
+/// Tokens can only be issued by admin connections, through
+/// `RlsConnection::issue_bypass_token`. Only hand them to code that must
+/// read and write every row, such as migrations, background jobs or admin
+/// tooling. The token's label is logged with every statement that bypasses
+/// row level security, so that such statements can be told apart from those
+/// of application users.
+#[derive(Debug, Clone)]
+pub struct BypassToken {
+    label: String,
+}
+
+impl BypassToken {
+    fn new(label: impl Into<String>) -> Self {
+        Self { label: label.into() }
+    }
+
+    /// The label identifying the code holding the token
+    pub fn label(&self) -> &str {
+        &self.label
+    }
 }
 
 impl RlsConnection {
@@ -40,7 +79,32 @@ impl RlsConnection {
     /// 
     /// A new RLS connection with the policy table initialized
     pub fn new(conn: Connection) -> Self {
-        Self { conn }
+        Self {
+            conn,
+            context: RwLock::new(SessionContext::default()),
+            strategy: RewriteStrategy::default(),
+            admin: false,
+            strict: None,
+            bypass: None,
+        }
+    }
+    
+    /// Create an RLS connection wrapper that bypasses row level security
+    /// 
+    /// No policy is applied to its statements, which are logged as bypassed
+    /// with the token's label. Session functions such as `current_user_id()`
+    /// still work. Access to the `_rls_*` catalog still requires
+    /// `set_admin(true)`.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `conn` - The libSQL connection to wrap
+    /// * `token` - The capability to bypass row level security
+    pub fn with_bypass(conn: Connection, token: BypassToken) -> Self {
+        Self {
+            bypass: Some(token),
+            ..Self::new(conn)
+        }
     }
     
     /// Initialize the RLS system by creating the required tables
@@ -49,21 +113,7 @@ impl RlsConnection {
     /// storage tables exist.
     pub async fn initialize(&self) -> Result<()> {
         // Create the policy table if it doesn't exist
-        self.conn.execute(
-            "CREATE TABLE IF NOT EXISTS _rls_policies (
-                id INTEGER PRIMARY KEY,
-                name TEXT NOT NULL,
-                schema_name TEXT,
-                table_name TEXT NOT NULL,
-                command TEXT NOT NULL,
-                using_expr TEXT,
-                check_expr TEXT,
-                UNIQUE(name, schema_name, table_name)
-            )",
-            params![],
-        ).await?;
-
-        Ok(())
+        PolicyManager::init_policy_table(&self.conn).await
     }
 
     /// Create a new RLS connection wrapper and initialize it
@@ -80,29 +130,268 @@ impl RlsConnection {
         Ok(rls_conn)
     }
     
-    /// Get policies for a given table
-    async fn get_policies_for_table(&self, table_name: &str) -> Result<Vec<Policy>> {
+    /// Choose how policies are added to queries
+    /// 
+    /// Defaults to `RewriteStrategy::DerivedTable`, which keeps the meaning
+    /// of outer joins.
+    pub fn set_rewrite_strategy(&mut self, strategy: RewriteStrategy) {
+        self.strategy = strategy;
+    }
+    
+    /// Mark the connection as used by an administrator, such as a migration
+    /// tool, rather than an application user
+    /// 
+    /// Only admin connections can create, alter and drop policies, change
+    /// the row level security of tables, and read or write the `_rls_*`
+    /// catalog tables directly. Policies still apply to their queries. Admin
+    /// connections are not strict unless `set_strict(true)` is called.
+    pub fn set_admin(&mut self, admin: bool) {
+        self.admin = admin;
+    }
+    
+    /// Issue a token for connections created with `with_bypass`, for the
+    /// code identified by `label`, e.g. `"migrations"`
+    /// 
+    /// Like creating a `BYPASSRLS` role, this requires an admin connection,
+    /// and fails with `Error::PermissionDenied` otherwise. Issued tokens are
+    /// logged under the `libsql_rls::bypass` target.
+    pub fn issue_bypass_token(&self, label: impl Into<String>) -> Result<BypassToken> {
+        if !self.admin {
+            return Err(Error::PermissionDenied("Only admin connections can issue bypass tokens".to_string()));
+        }
+        let token = BypassToken::new(label);
+        tracing::info!(target: "libsql_rls::bypass", label = %token.label, "bypass token issued");
+        Ok(token)
+    }
+    
+    /// Choose whether statements that can't be parsed or protected are
+    /// rejected
+    /// 
+    /// A strict connection fails with `Error::UnsupportedSql` on SQL that
+    /// sqlparser can't parse, multiple statements, and statements other than
+    /// queries, INSERT, UPDATE, DELETE, RLS statements, transaction control
+    /// and the DDL allowed by `sql_parser::is_known_safe_statement`. Otherwise
+    /// such statements run unchanged, without any policy applied. Defaults
+    /// to strict for non-admin connections.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = Some(strict);
+    }
+    
+    /// Whether statements that can't be parsed or protected are rejected
+    pub fn is_strict(&self) -> bool {
+        self.strict.unwrap_or(!self.admin)
+    }
+    
+    /// Set a session context value, readable in SQL as `current_setting('key')`
+    /// 
+    /// # Arguments
+    /// 
+    /// * `key` - The setting name, e.g. `app.tenant_id`
+    /// * `value` - The value to expose to policies
+    pub fn set_context(&self, key: impl Into<String>, value: impl Into<Value>) {
+        self.context.write().unwrap_or_else(PoisonError::into_inner).set(key, value);
+    }
+    
+    /// Set the current user, readable in SQL as `current_user_id()`
+    pub fn set_user(&self, user_id: impl Into<Value>) {
+        self.set_context(USER_ID_KEY, user_id);
+    }
+    
+    /// Set the current role, readable in SQL as `current_role()`
+    /// 
+    /// Only policies `TO PUBLIC`, to this role or to a role it is a member
+    /// of apply to the session.
+    pub fn set_role(&self, role: impl Into<String>) {
+        self.set_context(ROLE_KEY, role.into());
+    }
+    
+    /// Remove all session context values, e.g. before reusing the connection
+    /// for another request
+    pub fn clear_context(&self) {
+        self.context.write().unwrap_or_else(PoisonError::into_inner).clear();
+    }
+    
+    /// Get the policies of a table that apply to a command
+    /// 
+    /// Returns `None` when row level security does not apply to the table.
+    /// Otherwise policies `FOR ALL` are returned along with those for the
+    /// command, keeping only those applying to the session's effective
+    /// roles, and an empty list means every row is denied.
+    async fn get_policies_for_table(&self, table_name: &str, command: &str) -> Result<Option<Vec<Policy>>> {
+        if !PolicyManager::row_level_security_enabled(&self.conn, table_name).await? {
+            return Ok(None);
+        }
+        let roles = self.effective_roles().await?;
+        
+        // Owners are exempt from the policies of their tables unless they
+        // are forced
+        if let Some((owner, forced)) = PolicyManager::table_owner(&self.conn, table_name).await? {
+            if !forced && roles.contains(&owner) {
+                tracing::info!(
+                    target: "libsql_rls::bypass",
+                    table = table_name,
+                    owner = %owner,
+                    "table owner bypassed row level security"
+                );
+                return Ok(None);
+            }
+        }
+
         let mut rows = self.conn.query(
-            "SELECT name, schema_name, table_name, command, using_expr, check_expr 
+            "SELECT name, schema_name, table_name, kind, command, roles, using_expr, check_expr 
              FROM _rls_policies 
-             WHERE table_name = ? AND (command = 'ALL' OR command = 'SELECT')",
-            params![table_name],
+             WHERE table_name = ? AND (command = 'ALL' OR command = ?)",
+            params![table_name, command],
         ).await?;
         
         let mut policies = Vec::new();
         
         while let Some(row) = rows.next()? {
-            policies.push(Policy {
+            let policy = Policy {
                 name: row.get(0)?,
                 schema_name: row.get(1)?,
                 table_name: row.get(2)?,
-                command: row.get(3)?,
-                using_expr: row.get(4)?,
-                check_expr: row.get(5)?,
+                kind: row.get::<String>(3)?.parse()?,
+                command: row.get(4)?,
+                roles: row.get::<String>(5)?.split(',').map(str::to_string).collect(),
+                using_expr: row.get(6)?,
+                check_expr: row.get(7)?,
+            };
+            if policy.applies_to(&roles) {
+                policies.push(policy);
+            }
+        }
+        
+        Ok(Some(policies))
+    }
+    
+    /// Get the roles whose policies apply to the session: its current role,
+    /// the roles that role is a member of, and `PUBLIC`
+    async fn effective_roles(&self) -> Result<Vec<String>> {
+        role::effective_roles(&self.conn, self.current_role().as_deref()).await
+    }
+    
+    /// Get the session's current role, as set by `set_role`
+    fn current_role(&self) -> Option<String> {
+        match self.context.read().unwrap_or_else(PoisonError::into_inner).get(ROLE_KEY) {
+            Some(Value::Text(role)) => Some(role.clone()),
+            _ => None,
+        }
+    }
+    
+    /// Get why the session bypasses row level security, if it does
+    /// 
+    /// Sessions bypass it when the connection holds a `BypassToken` or the
+    /// current role has the `BYPASSRLS` attribute.
+    async fn bypass_reason(&self) -> Result<Option<String>> {
+        if let Some(token) = &self.bypass {
+            return Ok(Some(format!("bypass token {}", token.label)));
+        }
+        match self.current_role() {
+            Some(role) if role::has_bypass(&self.conn, &role).await? => {
+                Ok(Some(format!("role {} with BYPASSRLS", role)))
+            }
+            _ => Ok(None),
+        }
+    }
+    
+    /// Get the columns of a table in declaration order
+    async fn get_table_columns(&self, table_name: &str) -> Result<Vec<TableColumn>> {
+        let mut rows = self.conn.query(
+            "SELECT name, dflt_value FROM pragma_table_info(?)",
+            params![table_name],
+        ).await?;
+        
+        let mut columns = Vec::new();
+        
+        while let Some(row) = rows.next()? {
+            columns.push(TableColumn {
+                name: row.get(0)?,
+                default: row.get(1)?,
             });
         }
         
-        Ok(policies)
+        Ok(columns)
+    }
+    
+    /// Get the columns identifying the rows of a table: `rowid`, or the
+    /// primary key of a `WITHOUT ROWID` table
+    async fn get_row_key(&self, table_name: &str) -> Result<Vec<String>> {
+        let mut rows = self.conn.query("SELECT wr FROM pragma_table_list(?)", params![table_name]).await?;
+        let without_rowid = match rows.next()? {
+            Some(row) => row.get::<i64>(0)? != 0,
+            None => false,
+        };
+        if !without_rowid {
+            return Ok(vec!["rowid".to_string()]);
+        }
+        
+        let mut rows = self.conn.query(
+            "SELECT name FROM pragma_table_info(?) WHERE pk > 0 ORDER BY pk",
+            params![table_name],
+        ).await?;
+        let mut key = Vec::new();
+        while let Some(row) = rows.next()? {
+            key.push(row.get(0)?);
+        }
+        Ok(key)
+    }
+    
+    /// Get the column of a table that is an alias of its rowid: the primary
+    /// key of a rowid table, when it is a single column of type `INTEGER`
+    async fn get_rowid_alias(&self, table_name: &str) -> Result<Option<String>> {
+        if self.get_row_key(table_name).await? != ["rowid"] {
+            return Ok(None);
+        }
+        let mut rows = self.conn.query(
+            "SELECT name, upper(type) = 'INTEGER' FROM pragma_table_info(?) WHERE pk > 0",
+            params![table_name],
+        ).await?;
+        let mut primary_key = Vec::new();
+        while let Some(row) = rows.next()? {
+            primary_key.push((row.get::<String>(0)?, row.get::<bool>(1)?));
+        }
+        Ok(match primary_key.pop() {
+            Some((name, true)) if primary_key.is_empty() => Some(name),
+            _ => None,
+        })
+    }
+    
+    /// Get the column sets of a table's primary key and unique indexes
+    async fn get_unique_keys(&self, table_name: &str) -> Result<Vec<Vec<String>>> {
+        let mut rows = self.conn.query(
+            "SELECT name FROM pragma_table_info(?) WHERE pk > 0 ORDER BY pk",
+            params![table_name],
+        ).await?;
+        
+        let mut primary_key = Vec::new();
+        while let Some(row) = rows.next()? {
+            primary_key.push(row.get(0)?);
+        }
+        
+        // Expression columns have no name and can't be matched, so indexes
+        // using them are left out
+        let mut rows = self.conn.query(
+            "SELECT il.name, ii.name 
+             FROM pragma_index_list(?) AS il, pragma_index_info(il.name) AS ii 
+             WHERE il.\"unique\" = 1 AND il.partial = 0 
+             ORDER BY il.seq, ii.seqno",
+            params![table_name],
+        ).await?;
+        
+        let mut indexes: Vec<(String, Vec<Option<String>>)> = Vec::new();
+        while let Some(row) = rows.next()? {
+            let index: String = row.get(0)?;
+            let column: Option<String> = row.get(1)?;
+            match indexes.last_mut() {
+                Some((name, columns)) if *name == index => columns.push(column),
+                _ => indexes.push((index, vec![column])),
+            }
+        }
+        
+        let mut keys = vec![primary_key];
+        keys.extend(indexes.into_iter().filter_map(|(_, columns)| columns.into_iter().collect()));
+        Ok(keys)
     }
     
     /// Execute a SQL statement with RLS processing
@@ -119,79 +408,120 @@ impl RlsConnection {
     where
         P: IntoParams,
     {
-        // Check if it's a CREATE POLICY statement
-        if let Some(captures) = CREATE_POLICY_REGEX.captures(sql) {
+        // Check if it's an RLS statement such as CREATE POLICY
+        if let Some(statement) = rls_statement::parse_rls_statement(sql)? {
+            if !self.admin {
+                return Err(Error::PermissionDenied(
+                    "Only admin connections can manage row level security".to_string(),
+                ));
+            }
+            
             // Ensure the policy table exists
             self.initialize().await?;
 
-            // Parse the policy
-            let policy_name = captures.get(1).map_or("", |m| m.as_str()).to_string();
-            let table_ref = captures.get(2).map_or("", |m| m.as_str());
-            let command = captures.get(3).map_or("ALL", |m| m.as_str()).to_uppercase();
-            let using_expr = captures.get(4).map(|m| m.as_str().to_string());
-            let check_expr = captures.get(5).map(|m| m.as_str().to_string());
-            
-            // Parse table reference (with optional schema)
-            let (schema_name, table_name) = if table_ref.contains('.') {
-                let parts: Vec<&str> = table_ref.split('.').collect();
-                (Some(parts[0].to_string()), parts[1].to_string())
-            } else {
-                (None, table_ref.to_string())
-            };
-            
-            // Store the policy in the database
-            self.conn.execute(
-                "INSERT INTO _rls_policies (name, schema_name, table_name, command, using_expr, check_expr)
-                 VALUES (?, ?, ?, ?, ?, ?)",
-                params![
-                    policy_name,
-                    schema_name,
-                    table_name,
-                    command,
-                    using_expr,
-                    check_expr,
-                ],
-            ).await.map_err(Into::into)
+            self.execute_rls_statement(statement).await
         } else {
-            // Try to parse the SQL to apply RLS policies
-            match sql_parser::parse_sql(sql) {
-                Ok(mut stmt) => {
-                    // For now, we only handle SELECT statements
-                    if let Statement::Query(_) = &stmt {
-                        // Extract table references
-                        let tables = sql_parser::extract_table_references(&stmt);
-                        
-                        // Apply RLS policies for each referenced table
-                        let mut modified = false;
-                        for table in tables {
-                            let policies = self.get_policies_for_table(&table).await?;
-                            if !policies.is_empty() {
-                                sql_parser::apply_rls_to_select(&mut stmt, &policies)?;
-                                modified = true;
-                            }
-                        }
-                        
-                        if modified {
-                            // Compile the modified AST back to SQL
-                            let rewritten_sql = sql_parser::compile_ast_to_sql(&stmt);
-                            return self.conn.execute(&rewritten_sql, params_values).await.map_err(Into::into);
-                        }
+            // Apply RLS policies, falling back to the original SQL if nothing changed
+            let params_values = params_values.into_params()?;
+            match self.rewrite_sql(sql, &params_values).await? {
+                Some(rewritten) if rewritten.is_checked() => {
+                    self.conn.execute(&format!("SAVEPOINT {CHECK_SAVEPOINT}"), params![]).await?;
+                    let result = self.execute_checked(rewritten).await;
+                    self.end_savepoint(result.is_ok()).await?;
+                    result
+                }
+                Some(rewritten) => self.conn.execute(&rewritten.sql, rewritten.params).await.map_err(Into::into),
+                None => self.conn.execute(sql, params_values).await.map_err(Into::into),
+            }
+        }
+    }
+    
+    /// Execute a statement handled by the RLS layer itself
+    async fn execute_rls_statement(&self, statement: RlsStatement) -> Result<u64> {
+        match statement {
+            RlsStatement::CreatePolicy(policy) => {
+                role::ensure_roles_exist(&self.conn, &policy.roles).await?;
+                // Store the policy in the database
+                PolicyManager::insert_policy(&self.conn, &policy).await
+            }
+            RlsStatement::DropPolicy { name, schema_name, table_name, if_exists } => {
+                PolicyManager::drop_policy(&self.conn, &name, schema_name.as_deref(), &table_name, if_exists).await
+            }
+            RlsStatement::AlterPolicy { name, schema_name, table_name, change } => {
+                if let AlterPolicyChange::Update { roles: Some(roles), .. } = &change {
+                    role::ensure_roles_exist(&self.conn, roles).await?;
+                }
+                PolicyManager::alter_policy(&self.conn, &name, schema_name.as_deref(), &table_name, &change).await
+            }
+            RlsStatement::CreateMaskingPolicy(policy) => {
+                role::ensure_roles_exist(&self.conn, &policy.roles).await?;
+                PolicyManager::insert_masking_policy(&self.conn, &policy).await
+            }
+            RlsStatement::DropMaskingPolicy { name, table_name, if_exists } => {
+                PolicyManager::drop_masking_policy(&self.conn, &name, &table_name, if_exists).await
+            }
+            RlsStatement::AlterTableRowLevelSecurity { table_name, action } => {
+                PolicyManager::set_row_level_security(&self.conn, &table_name, action).await
+            }
+            RlsStatement::AlterTableOwner { table_name, owner } => {
+                if owner == PUBLIC_ROLE {
+                    return Err(Error::Policy("PUBLIC can't own a table".to_string()));
+                }
+                role::ensure_roles_exist(&self.conn, &[&owner]).await?;
+                PolicyManager::set_table_owner(&self.conn, &table_name, &owner).await
+            }
+            RlsStatement::CreateRole { name, bypass } => role::create_role(&self.conn, &name, bypass).await,
+            RlsStatement::AlterRole { name, bypass } => role::set_bypass(&self.conn, &name, bypass).await,
+            RlsStatement::DropRole { name, if_exists } => role::drop_role(&self.conn, &name, if_exists).await,
+            RlsStatement::GrantRole { roles, members } => {
+                let mut granted = 0;
+                for granted_role in &roles {
+                    for member in &members {
+                        granted += role::grant_role(&self.conn, granted_role, member).await?;
                     }
                 }
-                Err(_) => {
-                    // If we can't parse it, just pass it through
-                    // This allows DDL statements to work normally
+                Ok(granted)
+            }
+            RlsStatement::RevokeRole { roles, members } => {
+                let mut revoked = 0;
+                for revoked_role in &roles {
+                    for member in &members {
+                        revoked += role::revoke_role(&self.conn, revoked_role, member).await?;
+                    }
                 }
+                Ok(revoked)
+            }
+            RlsStatement::GrantPrivileges { privileges, tables, roles } => {
+                role::ensure_roles_exist(&self.conn, &roles).await?;
+                let mut granted = 0;
+                for table in &tables {
+                    for grantee in &roles {
+                        granted += privilege::grant_privileges(&self.conn, table, grantee, &privileges).await?;
+                    }
+                }
+                Ok(granted)
+            }
+            RlsStatement::RevokePrivileges { privileges, tables, roles } => {
+                role::ensure_roles_exist(&self.conn, &roles).await?;
+                let mut revoked = 0;
+                for table in &tables {
+                    for grantee in &roles {
+                        revoked += privilege::revoke_privileges(&self.conn, table, grantee, &privileges).await?;
+                    }
+                }
+                Ok(revoked)
             }
-            
-            // If we reach here, just execute the original SQL
-            self.conn.execute(sql, params_values).await.map_err(Into::into)
         }
     }
     
     /// Execute a query and return the rows
     /// 
-    /// Applies RLS to SELECT statements before execution.
+    /// Applies RLS to SELECT statements before execution. INSERT, UPDATE and
+    /// DELETE statements with a RETURNING clause are checked against their
+    /// table's policies as by `execute`, and only return rows the caller
+    /// could SELECT: UPDATE and DELETE skip hidden rows, while an INSERT or
+    /// UPDATE producing a row the SELECT policies reject fails with
+    /// `Error::PolicyViolation`.
     /// 
     /// # Arguments
     /// 
@@ -201,38 +531,672 @@ impl RlsConnection {
     where
         P: IntoParams,
     {
-        // Try to parse the SQL to apply RLS policies
-        match sql_parser::parse_sql(sql) {
-            Ok(mut stmt) => {
-                // For now, we only handle SELECT statements
-                if let Statement::Query(_) = &stmt {
-                    // Extract table references
-                    let tables = sql_parser::extract_table_references(&stmt);
-                    
-                    // Apply RLS policies for each referenced table
-                    let mut modified = false;
-                    for table in tables {
-                        let policies = self.get_policies_for_table(&table).await?;
-                        if !policies.is_empty() {
-                            sql_parser::apply_rls_to_select(&mut stmt, &policies)?;
-                            modified = true;
-                        }
+        // Apply RLS policies, falling back to the original SQL if nothing changed
+        let params_values = params_values.into_params()?;
+        match self.rewrite_sql(sql, &params_values).await? {
+            Some(rewritten) if rewritten.is_checked() => {
+                self.conn.execute(&format!("SAVEPOINT {CHECK_SAVEPOINT}"), params![]).await?;
+                let result = self.query_checked(rewritten).await;
+                self.end_savepoint(result.is_ok()).await?;
+                let (columns, rows) = result?;
+                self.stage_returned_rows(rows).await?;
+                self.conn.query(&returned_rows_sql(&columns), params![]).await.map_err(Into::into)
+            }
+            Some(rewritten) => self.conn.query(&rewritten.sql, rewritten.params).await.map_err(Into::into),
+            None => self.conn.query(sql, params_values).await.map_err(Into::into),
+        }
+    }
+    
+    /// Execute a statement with checks in the savepoint started by
+    /// `execute`, returning the number of rows it changed
+    async fn execute_checked(&self, rewritten: RewrittenStatement) -> Result<u64> {
+        self.run_checks(rewritten.checks).await?;
+        self.track_writes(rewritten.tracked.as_ref()).await?;
+        let changed = self.conn.execute(&rewritten.sql, rewritten.params).await?;
+        self.run_checks(rewritten.written_checks).await?;
+        self.untrack_writes(rewritten.tracked.as_ref()).await?;
+        Ok(changed)
+    }
+    
+    /// Run a statement with checks in the savepoint started by `query`,
+    /// returning the names of its columns and the rows it returned
+    /// 
+    /// SQLite can't release a savepoint while a statement is in progress, so
+    /// the rows are read in full before the savepoint is released.
+    async fn query_checked(&self, rewritten: RewrittenStatement) -> Result<(Vec<String>, Vec<Vec<Value>>)> {
+        self.run_checks(rewritten.checks).await?;
+        self.track_writes(rewritten.tracked.as_ref()).await?;
+        let mut rows = self.conn.query(&rewritten.sql, rewritten.params).await?;
+        let columns: Vec<String> = (0..rows.column_count())
+            .map(|index| rows.column_name(index).unwrap_or_default().to_string())
+            .collect();
+        let mut returned = Vec::new();
+        while let Some(row) = rows.next()? {
+            returned.push((0..columns.len() as i32).map(|index| row.get_value(index)).collect::<libsql::Result<_>>()?);
+        }
+        drop(rows);
+        self.run_checks(rewritten.written_checks).await?;
+        self.untrack_writes(rewritten.tracked.as_ref()).await?;
+        Ok((columns, returned))
+    }
+    
+    /// Store the rows returned by a statement with checks in
+    /// `RETURNED_TABLE`, in place of those of the previous one, for
+    /// `returned_rows_sql` to read back
+    /// 
+    /// Each value is stored as a row of its own, at most
+    /// `RETURNED_VALUES_PER_INSERT` per statement, as SQLite limits the
+    /// number of parameters of a statement.
+    async fn stage_returned_rows(&self, rows: Vec<Vec<Value>>) -> Result<()> {
+        self.conn.execute(
+            &format!(
+                "CREATE TEMP TABLE IF NOT EXISTS {RETURNED_TABLE} (
+                    row_index INTEGER NOT NULL,
+                    column_index INTEGER NOT NULL,
+                    value,
+                    PRIMARY KEY (row_index, column_index)
+                )"
+            ),
+            params![],
+        ).await?;
+        self.conn.execute(&format!("DELETE FROM temp.{RETURNED_TABLE}"), params![]).await?;
+        
+        let values: Vec<(String, Value)> = rows
+            .into_iter()
+            .enumerate()
+            .flat_map(|(row, values)| {
+                values.into_iter().enumerate().map(move |(column, value)| (format!("({row}, {column}, ?)"), value))
+            })
+            .collect();
+        for chunk in values.chunks(RETURNED_VALUES_PER_INSERT) {
+            let (placeholders, values): (Vec<_>, Vec<_>) = chunk.iter().cloned().unzip();
+            self.conn.execute(
+                &format!(
+                    "INSERT INTO temp.{RETURNED_TABLE} (row_index, column_index, value) VALUES {}",
+                    placeholders.join(", ")
+                ),
+                Params::Positional(values),
+            ).await?;
+        }
+        Ok(())
+    }
+    
+    /// Record the rows a statement writes to a table in
+    /// `policy_check::WRITTEN_TABLE`, for the checks of the written rows
+    /// 
+    /// Temporary triggers record the `policy_check::row_key` of each row
+    /// inserted or updated, along with the command, until `untrack_writes`
+    /// drops them. The table is created even without a table to record, as
+    /// `query` reads it.
+    async fn track_writes(&self, tracked: Option<&(Ident, Vec<String>)>) -> Result<()> {
+        let written = policy_check::WRITTEN_TABLE;
+        self.conn.execute(
+            &format!("CREATE TEMP TABLE IF NOT EXISTS {written} (command TEXT NOT NULL, row_key NOT NULL)"),
+            params![],
+        ).await?;
+        let Some((table, key)) = tracked else {
+            return Ok(());
+        };
+        let table = Ident::with_quote('"', &table.value);
+        let row_key = policy_check::row_key(&Ident::new("NEW"), key);
+        for command in ["INSERT", "UPDATE"] {
+            self.conn.execute(
+                &format!(
+                    "CREATE TEMP TRIGGER {written}_{command} AFTER {command} ON {table} BEGIN
+                         INSERT INTO {written} (command, row_key) VALUES ('{command}', {row_key});
+                     END"
+                ),
+                params![],
+            ).await?;
+        }
+        Ok(())
+    }
+    
+    /// Stop recording the rows written to a table and forget those recorded
+    async fn untrack_writes(&self, tracked: Option<&(Ident, Vec<String>)>) -> Result<()> {
+        if tracked.is_none() {
+            return Ok(());
+        }
+        let written = policy_check::WRITTEN_TABLE;
+        for command in ["INSERT", "UPDATE"] {
+            self.conn.execute(&format!("DROP TRIGGER temp.{written}_{command}"), params![]).await?;
+        }
+        self.conn.execute(&format!("DELETE FROM temp.{written}"), params![]).await?;
+        Ok(())
+    }
+    
+    /// End the savepoint of a statement with checks, undoing the statement
+    /// unless it passed them
+    async fn end_savepoint(&self, passed: bool) -> Result<()> {
+        // A failed statement may have rolled back the whole transaction,
+        // savepoint included, as with INSERT OR ROLLBACK
+        if self.conn.is_autocommit() {
+            return Ok(());
+        }
+        if !passed {
+            self.conn.execute(&format!("ROLLBACK TO {CHECK_SAVEPOINT}"), params![]).await?;
+        }
+        self.conn.execute(&format!("RELEASE {CHECK_SAVEPOINT}"), params![]).await?;
+        Ok(())
+    }
+    
+    /// Run the WITH CHECK queries of a statement, failing on the first
+    /// violating row
+    async fn run_checks(&self, checks: Vec<PreparedCheck>) -> Result<()> {
+        for PreparedCheck { check, sql, params } in checks {
+            let mut rows = self.conn.query(&sql, params).await?;
+            if let Some(row) = rows.next()? {
+                return Err(check.violation(&row)?);
+            }
+        }
+        Ok(())
+    }
+    
+    /// Rewrite a SQL statement to apply RLS policies and session values
+    /// 
+    /// Returns the rewritten SQL together with the parameters to bind: the
+    /// caller's own parameters, renumbered positionally, followed by the
+    /// session context values referenced by the statement and its policies.
+    /// Data-modifying statements also get the WITH CHECK queries to run
+    /// before them. Returns `None` when the caller's SQL can run as is:
+    /// when a connection that isn't strict can't parse it, so that special
+    /// queries pass through, or when the statement of an admin connection
+    /// that isn't strict does not need to change.
+    async fn rewrite_sql(&self, sql: &str, params_values: &Params) -> Result<Option<RewrittenStatement>> {
+        let bypass = self.bypass_reason().await?;
+        if let Some(reason) = &bypass {
+            tracing::info!(target: "libsql_rls::bypass", reason = %reason, sql, "statement bypassed row level security");
+        }
+        
+        let parsed = sql_parser::number_placeholders(sql)
+            .and_then(|(numbered_sql, names)| Ok((sql_parser::parse_sql(&numbered_sql)?, names)));
+        let (mut stmt, names) = match parsed {
+            Ok(parsed) => parsed,
+            Err(_) if !self.admin && sql_parser::mentions_catalog(sql) => return Err(catalog_denied()),
+            Err(e) if self.is_strict() && bypass.is_none() => {
+                return Err(Error::UnsupportedSql(format!("Statement can't be checked for row level security: {e}")));
+            }
+            Err(_) => return Ok(None),
+        };
+        if !self.admin && sql_parser::references_catalog(&stmt) {
+            return Err(catalog_denied());
+        }
+        self.check_schema_change(&stmt).await?;
+        
+        // Privileges are checked even when policies are bypassed
+        let mut modified = self.check_privileges(&mut stmt).await?;
+        let mut checks = Checks::default();
+        
+        match &stmt {
+            // Session values are still bound below
+            _ if bypass.is_some() => {}
+            Statement::Query(_) => {
+                let policies = self.get_select_policies(&stmt).await?;
+                let masks = self.get_masks(&stmt).await?;
+                modified |= sql_parser::apply_rls_to_statement(&mut stmt, &policies, &masks, self.strategy)?;
+            }
+            Statement::Insert { .. } => {
+                // Filter the source first, so that the checks see the rows
+                // that are actually inserted
+                let policies = self.get_select_policies(&stmt).await?;
+                let masks = self.get_masks(&stmt).await?;
+                modified |= sql_parser::apply_rls_to_statement(&mut stmt, &policies, &masks, self.strategy)?;
+                
+                let Statement::Insert { or, table_name, columns, source, on, returning, .. } = &stmt else {
+                    unreachable!("the statement is an INSERT");
+                };
+                if let Some(table) = table_name.0.last() {
+                    let key = self.get_row_key(&table.value).await?;
+                    checks.written.extend(self.insert_checks(table, &key, returning.is_some()).await?);
+                    if let Some(SqliteOnConflict::Replace) = or {
+                        checks.before.extend(self.replace_check(table, columns, source).await?);
+                    }
+                    if let Some(OnInsert::OnConflict(OnConflict {
+                        conflict_target,
+                        action: OnConflictAction::DoUpdate(_),
+                    })) = on
+                    {
+                        let conflict_target = conflict_target.as_ref();
+                        self.upsert_checks(table, &key, columns, source, conflict_target, &mut checks).await?;
                     }
-                    
-                    if modified {
-                        // Compile the modified AST back to SQL
-                        let rewritten_sql = sql_parser::compile_ast_to_sql(&stmt);
-                        return self.conn.query(&rewritten_sql, params_values).await.map_err(Into::into);
+                    checks.target = Some((table.clone(), key));
+                }
+            }
+            Statement::Update { returning, .. } | Statement::Delete { returning, .. } => {
+                let returning = returning.is_some();
+                let target = sql_parser::target_table(&stmt).cloned();
+                let policies = self.get_select_policies(&stmt).await?;
+                let masks = self.get_masks(&stmt).await?;
+                modified |= sql_parser::apply_rls_to_statement(&mut stmt, &policies, &masks, self.strategy)?;
+                if let Some(target) = target {
+                    if returning {
+                        modified |= self.restrict_to_visible(&mut stmt, &target).await?;
                     }
+                    if let Statement::Update { .. } = stmt {
+                        let key = self.get_row_key(&target.value).await?;
+                        checks.written.extend(self.restrict_update(&mut stmt, &target, &key, returning).await?);
+                        checks.target = Some((target, key));
+                    } else {
+                        modified |= self.restrict_delete(&mut stmt, &target).await?;
+                    }
+                } else if self.is_strict() {
+                    return Err(Error::UnsupportedSql(
+                        "Only UPDATE and DELETE of a single table are supported".to_string(),
+                    ));
+                }
+            }
+            _ if !self.is_strict() || sql_parser::is_known_safe_statement(&stmt) => {}
+            _ => {
+                return Err(Error::UnsupportedSql(format!(
+                    "Statement is not allowed in strict mode: {stmt}"
+                )));
+            }
+        }
+        
+        // Bind current_user_id(), policy placeholders and friends after the
+        // caller's own parameters
+        let mut values = positional_values(params_values, &names);
+        values.resize(values.len().max(names.len()), Value::Null);
+        let context = self.context.read().unwrap_or_else(PoisonError::into_inner).clone();
+        let session_values = sql_parser::bind_session_values(&mut stmt, &context, values.len() + 1)?;
+        modified |= !session_values.is_empty() || !checks.is_empty();
+        
+        // Strict and non-admin connections run the statement as parsed,
+        // never the caller's text, which SQLite may read differently
+        if !modified && self.admin && !self.is_strict() {
+            return Ok(None);
+        }
+        
+        let prepare = |checks: Vec<PolicyCheck>| {
+            checks
+                .into_iter()
+                .map(|mut check| {
+                    let mut params = values.clone();
+                    params.extend(sql_parser::bind_session_values(&mut check.statement, &context, values.len() + 1)?);
+                    // SQLite rejects parameters beyond the highest one a statement uses
+                    params.truncate(sql_parser::max_placeholder_index(&check.statement));
+                    Ok(PreparedCheck {
+                        sql: sql_parser::compile_ast_to_sql(&check.statement),
+                        params: Params::Positional(params),
+                        check,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()
+        };
+        let before = prepare(checks.before)?;
+        let written = prepare(checks.written)?;
+        // Only the rows of statements with checks of written rows are recorded
+        let tracked = checks.target.filter(|_| !written.is_empty());
+        
+        values.extend(session_values);
+        // Compile the modified AST back to SQL
+        Ok(Some(RewrittenStatement {
+            sql: sql_parser::compile_ast_to_sql(&stmt),
+            params: Params::Positional(values),
+            checks: before,
+            written_checks: written,
+            tracked,
+        }))
+    }
+    
+    /// Fail unless the session may change the schema as a statement does
+    /// 
+    /// Only admin connections can create temporary tables, which would hide
+    /// the tables policies read, and drop or index tables with row level
+    /// security enabled. Dropping one would remove every row its DELETE
+    /// policies protect, and a unique index would let one session block
+    /// and probe the inserts of the others. Tables under privilege checks
+    /// can only be dropped or indexed by their owner.
+    async fn check_schema_change(&self, stmt: &Statement) -> Result<()> {
+        if self.admin {
+            return Ok(());
+        }
+        if sql_parser::creates_temporary_table(stmt) {
+            return Err(Error::PermissionDenied("Only admin connections can create temporary tables".to_string()));
+        }
+        for table in sql_parser::dropped_or_indexed_tables(stmt) {
+            if PolicyManager::row_level_security_enabled(&self.conn, &table).await? {
+                return Err(Error::PermissionDenied(format!(
+                    "Only admin connections can drop or index table {}, which has row level security enabled",
+                    table
+                )));
+            }
+            if !privilege::may_change_table(&self.conn, &table, &self.effective_roles().await?).await? {
+                return Err(Error::PermissionDenied(format!("Only the owner of table {} can drop or index it", table)));
+            }
+        }
+        Ok(())
+    }
+    
+    /// Fail unless the session holds the privileges a statement needs on
+    /// every table it references
+    /// 
+    /// Tables the session may only read some columns of are restricted to
+    /// those, expanding `*` into the readable columns, in which case the
+    /// statement is changed and `true` returned.
+    async fn check_privileges(&self, stmt: &mut Statement) -> Result<bool> {
+        let required = sql_parser::required_privileges(stmt);
+        if required.is_empty() {
+            return Ok(false);
+        }
+        let roles = self.effective_roles().await?;
+        let mut restricted = HashMap::new();
+        for (table, required) in required {
+            match privilege::table_access(&self.conn, &table, &roles, required).await? {
+                Access::Table => {}
+                Access::Columns(columns) => {
+                    let (permitted, forbidden) = self
+                        .get_table_columns(&table)
+                        .await?
+                        .into_iter()
+                        .map(|column| column.name)
+                        .partition(|column| columns.iter().any(|granted| granted.eq_ignore_ascii_case(column)));
+                    let rowid_alias = self.get_rowid_alias(&table).await?;
+                    restricted.insert(table, ReadableColumns { permitted, forbidden, rowid_alias });
+                }
+                Access::Denied => {
+                    return Err(Error::PermissionDenied(format!("{} on table {}", required, table)));
                 }
             }
-            Err(_) => {
-                // If we can't parse it, just pass it through
-                // This allows special queries to work normally
+        }
+        if restricted.is_empty() {
+            return Ok(false);
+        }
+        sql_parser::restrict_columns(stmt, &restricted)
+    }
+    
+    /// Get the SELECT policies of every protected table a statement reads
+    /// 
+    /// Enabled tables without policies map to an empty list, which
+    /// `sql_parser::apply_rls_to_query` turns into `WHERE false` so that they
+    /// fail closed.
+    async fn get_select_policies(&self, stmt: &Statement) -> Result<HashMap<String, Vec<Policy>>> {
+        let mut policies = HashMap::new();
+        for table in sql_parser::extract_table_references(stmt) {
+            if let Some(table_policies) = self.get_policies_for_table(&table, "SELECT").await? {
+                policies.insert(table, table_policies);
             }
         }
+        Ok(policies)
+    }
+    
+    /// Get the columns of every table with masking policies for the
+    /// session's effective roles a statement references, along with their
+    /// masks
+    async fn get_masks(&self, stmt: &Statement) -> Result<HashMap<String, Vec<MaskedColumn>>> {
+        let mut masks = HashMap::new();
+        let mut roles = None;
+        for table in sql_parser::extract_table_references(stmt) {
+            let mut policies = PolicyManager::masking_policies(&self.conn, &table).await?;
+            if policies.is_empty() {
+                continue;
+            }
+            let roles = match &roles {
+                Some(roles) => roles,
+                None => roles.insert(self.effective_roles().await?),
+            };
+            policies.retain(|policy| policy.applies_to(roles));
+            if policies.is_empty() {
+                continue;
+            }
+            let mut columns = Vec::new();
+            for column in self.get_table_columns(&table).await? {
+                let mask = policies
+                    .iter()
+                    .find(|policy| policy.column_name.eq_ignore_ascii_case(&column.name))
+                    .map(|policy| sql_parser::parse_policy_expression(&policy.mask_expr))
+                    .transpose()?;
+                columns.push(MaskedColumn { name: column.name, mask });
+            }
+            masks.insert(table, columns);
+        }
+        Ok(masks)
+    }
+    
+    /// Apply the UPDATE policies of the target table to an UPDATE
+    /// 
+    /// Their USING expressions restrict which rows are updated, and the
+    /// returned checks verify the updated rows against their WITH CHECK
+    /// expressions and, when the rows are returned, against the SELECT
+    /// policies.
+    /// 
+    /// Updated rows are identified by `key`, as for `track_writes`.
+    async fn restrict_update(
+        &self,
+        stmt: &mut Statement,
+        table: &Ident,
+        key: &[String],
+        returning: bool,
+    ) -> Result<Vec<PolicyCheck>> {
+        let mut checks = Vec::new();
+        if returning {
+            checks.extend(self.visible_check(table, policy_check::written_rows(table, key, None)).await?);
+        }
+        if let Some(policies) = self.get_policies_for_table(&table.value, "UPDATE").await? {
+            sql_parser::restrict_target_rows(stmt, sql_parser::combine_policy_expressions(&policies)?);
+            let rows = policy_check::written_rows(table, key, Some("UPDATE"));
+            checks.push(PolicyCheck::new(&table.value, table.clone(), rows, &policies)?);
+        }
+        Ok(checks)
+    }
+    
+    /// Restrict the rows a DELETE removes with the USING expressions of the
+    /// target table's DELETE policies, returning whether it was restricted
+    async fn restrict_delete(&self, stmt: &mut Statement, table: &Ident) -> Result<bool> {
+        let Some(policies) = self.get_policies_for_table(&table.value, "DELETE").await? else {
+            return Ok(false);
+        };
+        sql_parser::restrict_target_rows(stmt, sql_parser::combine_policy_expressions(&policies)?);
+        Ok(true)
+    }
+    
+    /// Build the checks of the rows an INSERT inserts against the target
+    /// table's INSERT policies and, when the rows are returned, of every row
+    /// it writes against its SELECT policies
+    /// 
+    /// Written rows are identified by `key`, as for `track_writes`.
+    async fn insert_checks(&self, table: &Ident, key: &[String], returning: bool) -> Result<Vec<PolicyCheck>> {
+        let mut checks = Vec::new();
+        if let Some(policies) = self.get_policies_for_table(&table.value, "INSERT").await? {
+            let rows = policy_check::written_rows(table, key, Some("INSERT"));
+            checks.push(PolicyCheck::new(&table.value, table.clone(), rows, &policies)?);
+        }
+        if returning {
+            checks.extend(self.visible_check(table, policy_check::written_rows(table, key, None)).await?);
+        }
+        Ok(checks)
+    }
+    
+    /// Restrict the rows a statement with RETURNING targets to those the
+    /// caller can SELECT, returning whether it was restricted
+    /// 
+    /// As in Postgres, rows hidden by the SELECT policies are skipped rather
+    /// than modified without being returned.
+    async fn restrict_to_visible(&self, stmt: &mut Statement, table: &Ident) -> Result<bool> {
+        let Some(policies) = self.get_policies_for_table(&table.value, "SELECT").await? else {
+            return Ok(false);
+        };
+        sql_parser::restrict_target_rows(stmt, sql_parser::combine_policy_expressions(&policies)?);
+        Ok(true)
+    }
+    
+    /// Build the check that rows a statement writes and returns are visible
+    /// under the table's SELECT policies
+    /// 
+    /// Returning a new row the caller could not SELECT is a violation, as
+    /// in Postgres.
+    async fn visible_check(&self, table: &Ident, rows: Query) -> Result<Option<PolicyCheck>> {
+        let Some(policies) = self.get_policies_for_table(&table.value, "SELECT").await? else {
+            return Ok(None);
+        };
+        PolicyCheck::using(&table.value, table.clone(), rows, &policies).map(Some)
+    }
+    
+    /// Build the check of the rows a `REPLACE` deletes against the target
+    /// table's DELETE policies
+    /// 
+    /// `REPLACE INTO` and `INSERT OR REPLACE` delete the existing rows that
+    /// conflict with a proposed row on any unique key before inserting it,
+    /// so each of them must pass the USING expressions as if deleted by a
+    /// DELETE. A hidden row can't be left in place, so it is a violation.
+    async fn replace_check(&self, table: &Ident, columns: &[Ident], source: &Query) -> Result<Option<PolicyCheck>> {
+        let Some(policies) = self.get_policies_for_table(&table.value, "DELETE").await? else {
+            return Ok(None);
+        };
+        
+        let keys = self.get_unique_keys(&table.value).await?;
+        let table_columns = self.get_table_columns(&table.value).await?;
+        let proposed = policy_check::insert_rows(&table_columns, columns, source.clone())?;
+        let existing = policy_check::conflicting_rows(table, &keys, proposed);
+        PolicyCheck::using(&table.value, table.clone(), existing, &policies).map(Some)
+    }
+    
+    /// Build the checks of an upsert's `DO UPDATE` against the target
+    /// table's INSERT and UPDATE policies
+    /// 
+    /// As in Postgres, the proposed rows must pass the WITH CHECK
+    /// expressions of the INSERT policies even when they conflict, the
+    /// existing rows the upsert conflicts with must pass the USING
+    /// expressions of the UPDATE policies, and the rows it updates their
+    /// WITH CHECK expressions. Either failure is a violation, rather than
+    /// the row being silently skipped.
+    async fn upsert_checks(
+        &self,
+        table: &Ident,
+        key: &[String],
+        columns: &[Ident],
+        source: &Query,
+        conflict_target: Option<&ConflictTarget>,
+        checks: &mut Checks,
+    ) -> Result<()> {
+        let insert_policies = self.get_policies_for_table(&table.value, "INSERT").await?;
+        let update_policies = self.get_policies_for_table(&table.value, "UPDATE").await?;
+        if insert_policies.is_none() && update_policies.is_none() {
+            return Ok(());
+        }
         
-        // If we reach here, just execute the original SQL
-        self.conn.query(sql, params_values).await.map_err(Into::into)
+        let table_columns = self.get_table_columns(&table.value).await?;
+        let proposed = policy_check::insert_rows(&table_columns, columns, source.clone())?;
+        if let Some(policies) = insert_policies {
+            checks.before.push(PolicyCheck::new(&table.value, table.clone(), proposed.clone(), &policies)?);
+        }
+        if let Some(policies) = update_policies {
+            let keys = match conflict_target {
+                Some(ConflictTarget::Columns(target)) => {
+                    vec![target.iter().map(|column| column.value.clone()).collect()]
+                }
+                _ => self.get_unique_keys(&table.value).await?,
+            };
+            let existing = policy_check::conflicting_rows(table, &keys, proposed);
+            checks.before.push(PolicyCheck::using(&table.value, table.clone(), existing, &policies)?);
+            let updated = policy_check::written_rows(table, key, Some("UPDATE"));
+            checks.written.push(PolicyCheck::new(&table.value, table.clone(), updated, &policies)?);
+        }
+        Ok(())
     }
-} 
\ No newline at end of file
+}
+
+/// The checks of a data-modifying statement, as they are built
+#[derive(Default)]
+struct Checks {
+    /// Checks of the existing or proposed rows, run before the statement
+    before: Vec<PolicyCheck>,
+    /// Checks of the rows the statement writes, run after it
+    written: Vec<PolicyCheck>,
+    /// The table the statement writes, along with the columns identifying
+    /// its rows
+    target: Option<(Ident, Vec<String>)>,
+}
+
+impl Checks {
+    fn is_empty(&self) -> bool {
+        self.before.is_empty() && self.written.is_empty()
+    }
+}
+
+/// A statement rewritten to enforce RLS
+struct RewrittenStatement {
+    sql: String,
+    params: Params,
+    /// WITH CHECK queries to run before the statement
+    checks: Vec<PreparedCheck>,
+    /// WITH CHECK queries of the rows the statement writes, run after it
+    written_checks: Vec<PreparedCheck>,
+    /// The table whose written rows are recorded for `written_checks`,
+    /// along with the columns identifying them
+    tracked: Option<(Ident, Vec<String>)>,
+}
+
+impl RewrittenStatement {
+    fn is_checked(&self) -> bool {
+        !self.checks.is_empty() || !self.written_checks.is_empty()
+    }
+}
+
+/// A WITH CHECK query compiled together with its parameters
+struct PreparedCheck {
+    check: PolicyCheck,
+    sql: String,
+    params: Params,
+}
+
+/// The savepoint statements with checks run in, so that they can be undone
+/// when a check fails after they have run
+const CHECK_SAVEPOINT: &str = "_rls_check";
+
+/// The temporary table holding the rows returned by a statement with checks
+const RETURNED_TABLE: &str = "_rls_returned";
+
+/// How many returned values `RlsConnection::stage_returned_rows` binds to
+/// each statement, well below SQLite's limit on parameters
+const RETURNED_VALUES_PER_INSERT: usize = 500;
+
+/// Build a query returning the rows staged by
+/// `RlsConnection::stage_returned_rows`, with the given columns
+/// 
+/// ```sql
+/// SELECT max(CASE column_index WHEN 0 THEN value END) AS "a", ...
+/// FROM temp._rls_returned GROUP BY row_index ORDER BY row_index
+/// ```
+/// 
+/// The rows of a statement without RETURNING have no columns, which only
+/// a statement other than SELECT reproduces.
+fn returned_rows_sql(columns: &[String]) -> String {
+    if columns.is_empty() {
+        return format!("DELETE FROM temp.{RETURNED_TABLE} WHERE false");
+    }
+    let projection = columns
+        .iter()
+        .enumerate()
+        .map(|(index, column)| {
+            format!("max(CASE column_index WHEN {index} THEN value END) AS {}", Ident::with_quote('"', column))
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!("SELECT {projection} FROM temp.{RETURNED_TABLE} GROUP BY row_index ORDER BY row_index")
+}
+
+/// The error for a statement of a connection that isn't admin naming the
+/// RLS catalog
+fn catalog_denied() -> Error {
+    Error::PermissionDenied("Only admin connections can access the row level security catalog".to_string())
+}
+
+/// Convert the caller's parameters into positional values
+/// 
+/// `names` holds the named placeholder, if any, at each parameter index as
+/// returned by `sql_parser::number_placeholders`.
+fn positional_values(params_values: &Params, names: &[Option<String>]) -> Vec<Value> {
+    match params_values {
+        Params::None => Vec::new(),
+        Params::Positional(values) => values.clone(),
+        Params::Named(named) => names
+            .iter()
+            .map(|name| {
+                name.as_ref()
+                    .and_then(|name| named.iter().find(|(key, _)| key == name))
+                    .map_or(Value::Null, |(_, value)| value.clone())
+            })
+            .collect(),
+    }
+}
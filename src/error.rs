@@ -14,6 +14,19 @@ pub enum Error {
     #[error("Policy error: {0}")]
     Policy(String),
     
+    #[error("Permission denied: {0}")]
+    PermissionDenied(String),
+    
+    #[error(
+        "Row violates row-level security policy {}for table {table}",
+        .policy.as_ref().map(|policy| format!("{} ", policy)).unwrap_or_default()
+    )]
+    PolicyViolation {
+        /// The violated policy, if a single policy is to blame
+        policy: Option<String>,
+        table: String,
+    },
+    
     #[error("Tokenizer error: {0}")]
     TokenizerError(#[from] sqlparser::tokenizer::TokenizerError),
 } 
\ No newline at end of file
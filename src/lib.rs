@@ -2,9 +2,15 @@ mod policy;
 mod error;
 mod connection;
 mod sql_parser;
+mod policy_check;
+mod rls_statement;
+mod privilege;
+mod role;
+mod session;
 
-pub use connection::RlsConnection;
+pub use connection::{BypassToken, RlsConnection};
 pub use error::Error;
-pub use policy::{Policy, PolicyManager};
+pub use policy::{Policy, PolicyKind, PolicyManager};
+pub use sql_parser::RewriteStrategy;
 
 pub type Result<T> = std::result::Result<T, Error>; 
\ No newline at end of file
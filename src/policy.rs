@@ -1,25 +1,98 @@
-use crate::Result;
+use crate::{
+    rls_statement::{self, AlterPolicyChange, RowLevelSecurityAction},
+    role::PUBLIC_ROLE,
+    Error, Result,
+};
 use libsql::{Connection, params};
-use regex::Regex;
-use lazy_static::lazy_static;
+use std::fmt;
+use std::str::FromStr;
 
-lazy_static! {
-    // Basic regex pattern for CREATE POLICY statements
-    static ref CREATE_POLICY_REGEX: Regex = Regex::new(
-        r"(?i)CREATE\s+POLICY\s+(\w+)\s+ON\s+([\w\.]+)(?:\s+FOR\s+(\w+))?(?:\s+USING\s+\((.*?)\))?(?:\s+WITH\s+CHECK\s+\((.*?)\))?").unwrap();
+/// How a policy combines with the other policies on the same table
+///
+/// As in Postgres, permissive policies are OR'd together and restrictive
+/// policies are AND'd onto the result, so a row is visible only if at least
+/// one permissive policy and every restrictive policy allows it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PolicyKind {
+    #[default]
+    Permissive,
+    Restrictive,
 }
 
+impl fmt::Display for PolicyKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PolicyKind::Permissive => f.write_str("PERMISSIVE"),
+            PolicyKind::Restrictive => f.write_str("RESTRICTIVE"),
+        }
+    }
+}
+
+impl FromStr for PolicyKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.eq_ignore_ascii_case("PERMISSIVE") {
+            Ok(PolicyKind::Permissive)
+        } else if s.eq_ignore_ascii_case("RESTRICTIVE") {
+            Ok(PolicyKind::Restrictive)
+        } else {
+            Err(Error::Policy(format!("Unknown policy kind: {}", s)))
+        }
+    }
+}
+
+/// The prefix of the tables holding the RLS catalog, such as `_rls_policies`
+pub(crate) const CATALOG_PREFIX: &str = "_rls_";
+
 /// Represents a row-level security policy
 #[derive(Debug, Clone)]
 pub struct Policy {
     pub name: String,
     pub schema_name: Option<String>,
     pub table_name: String,
+    pub kind: PolicyKind,
     pub command: String, // SELECT, INSERT, UPDATE, DELETE, or ALL
+    /// The roles the policy applies to, `PUBLIC` for every role
+    pub roles: Vec<String>,
     pub using_expr: Option<String>,
     pub check_expr: Option<String>,
 }
 
+impl Policy {
+    /// Check whether the policy applies to a session with the given
+    /// effective roles
+    pub fn applies_to(&self, roles: &[String]) -> bool {
+        self.roles
+            .iter()
+            .any(|role| role == PUBLIC_ROLE || roles.contains(role))
+    }
+}
+
+/// A column masking policy, replacing a column's values with an
+/// expression in every query reading them
+#[derive(Debug, Clone)]
+pub struct MaskingPolicy {
+    pub name: String,
+    pub table_name: String,
+    pub column_name: String,
+    /// The roles the mask applies to, `PUBLIC` for every role
+    pub roles: Vec<String>,
+    /// The value read in place of the column's, which may refer to the
+    /// column itself, e.g. `substr(email, 1, 1) || '***'`
+    pub mask_expr: String,
+}
+
+impl MaskingPolicy {
+    /// Check whether the mask applies to a session with the given effective
+    /// roles
+    pub fn applies_to(&self, roles: &[String]) -> bool {
+        self.roles
+            .iter()
+            .any(|role| role == PUBLIC_ROLE || roles.contains(role))
+    }
+}
+
 /// Manages the creation, storage, and retrieval of RLS policies
 pub struct PolicyManager {
     conn: Connection,
@@ -33,28 +106,98 @@ impl PolicyManager {
         Ok(Self { conn })
     }
 
-    /// Initialize the policy table if it doesn't exist
-    async fn init_policy_table(conn: &Connection) -> Result<()> {
+    /// Initialize the policy tables if they don't exist
+    ///
+    /// `_rls_policies` holds the policies themselves and `_rls_tables` the
+    /// row level security settings and owner of each table altered with
+    /// `ALTER TABLE`. A NULL `enabled` means the table was never enabled
+    /// or disabled, and `privileges_managed` marks tables whose privileges
+    /// are checked against `_rls_grants` and `_rls_column_grants`.
+    /// `_rls_roles` holds the roles policies can apply to, and
+    /// `_rls_role_members` which roles are members of which, as granted
+    /// with `GRANT role TO member`. `_rls_masking_policies` holds the
+    /// masks of columns, at most one per column. Table names compare
+    /// without regard to case, as SQLite's do.
+    pub(crate) async fn init_policy_table(conn: &Connection) -> Result<()> {
         conn.execute(
             "CREATE TABLE IF NOT EXISTS _rls_policies (
                 id INTEGER PRIMARY KEY,
                 name TEXT NOT NULL,
                 schema_name TEXT,
-                table_name TEXT NOT NULL,
+                table_name TEXT NOT NULL COLLATE NOCASE,
+                kind TEXT NOT NULL DEFAULT 'PERMISSIVE',
                 command TEXT NOT NULL,
+                roles TEXT NOT NULL DEFAULT 'PUBLIC',
                 using_expr TEXT,
                 check_expr TEXT,
                 UNIQUE(name, schema_name, table_name)
             )",
             params![],
         ).await?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS _rls_tables (
+                table_name TEXT PRIMARY KEY COLLATE NOCASE,
+                enabled INTEGER,
+                forced INTEGER NOT NULL DEFAULT 0,
+                owner TEXT,
+                privileges_managed INTEGER NOT NULL DEFAULT 0
+            )",
+            params![],
+        ).await?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS _rls_roles (
+                name TEXT PRIMARY KEY,
+                bypass INTEGER NOT NULL DEFAULT 0
+            )",
+            params![],
+        ).await?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS _rls_role_members (
+                role TEXT NOT NULL,
+                member TEXT NOT NULL,
+                PRIMARY KEY (role, member)
+            )",
+            params![],
+        ).await?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS _rls_grants (
+                table_name TEXT NOT NULL COLLATE NOCASE,
+                role TEXT NOT NULL,
+                privilege TEXT NOT NULL,
+                PRIMARY KEY (table_name, role, privilege)
+            )",
+            params![],
+        ).await?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS _rls_masking_policies (
+                id INTEGER PRIMARY KEY,
+                name TEXT NOT NULL,
+                table_name TEXT NOT NULL COLLATE NOCASE,
+                column_name TEXT NOT NULL,
+                roles TEXT NOT NULL DEFAULT 'PUBLIC',
+                mask_expr TEXT NOT NULL,
+                UNIQUE(name, table_name),
+                UNIQUE(table_name, column_name)
+            )",
+            params![],
+        ).await?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS _rls_column_grants (
+                table_name TEXT NOT NULL COLLATE NOCASE,
+                role TEXT NOT NULL,
+                privilege TEXT NOT NULL,
+                column_name TEXT NOT NULL,
+                PRIMARY KEY (table_name, role, privilege, column_name)
+            )",
+            params![],
+        ).await?;
         Ok(())
     }
 
     /// Parse a CREATE POLICY statement and store it in the policy table
     pub async fn create_policy(&self, sql: &str) -> Result<Policy> {
         // Parse the policy from the SQL statement
-        let policy = Self::parse_create_policy(sql)?;
+        let policy = rls_statement::parse_create_policy(sql)?;
         
         // Store the policy in the database
         self.store_policy(&policy).await?;
@@ -62,58 +205,235 @@ impl PolicyManager {
         Ok(policy)
     }
     
-    /// Parse a CREATE POLICY statement using regular expressions
-    fn parse_create_policy(sql: &str) -> Result<Policy> {
-        // Use regex to extract policy details
-        if let Some(captures) = CREATE_POLICY_REGEX.captures(sql) {
-            let policy_name = captures.get(1).map_or("", |m| m.as_str()).to_string();
-            let table_ref = captures.get(2).map_or("", |m| m.as_str());
-            let command = captures.get(3).map_or("ALL", |m| m.as_str()).to_uppercase();
-            let using_expr = captures.get(4).map(|m| m.as_str().to_string());
-            let check_expr = captures.get(5).map(|m| m.as_str().to_string());
-            
-            // Parse table reference (with optional schema)
-            let (schema_name, table_name) = if table_ref.contains('.') {
-                let parts: Vec<&str> = table_ref.split('.').collect();
-                (Some(parts[0].to_string()), parts[1].to_string())
-            } else {
-                (None, table_ref.to_string())
-            };
-            
-            Ok(Policy {
-                name: policy_name,
-                schema_name,
-                table_name,
-                command,
-                using_expr,
-                check_expr,
-            })
-        } else {
-            Err(crate::Error::Policy("Invalid CREATE POLICY statement format".to_string()))
-        }
-    }
-    
     /// Store a policy in the database
     async fn store_policy(&self, policy: &Policy) -> Result<()> {
-        self.conn.execute(
-            "INSERT INTO _rls_policies (name, schema_name, table_name, command, using_expr, check_expr)
-             VALUES (?, ?, ?, ?, ?, ?)",
+        Self::insert_policy(&self.conn, policy).await?;
+        Ok(())
+    }
+
+    /// Insert a policy into the policy table of the given connection
+    pub(crate) async fn insert_policy(conn: &Connection, policy: &Policy) -> Result<u64> {
+        conn.execute(
+            "INSERT INTO _rls_policies (name, schema_name, table_name, kind, command, roles, using_expr, check_expr)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
             params![
                 policy.name.clone(),
                 policy.schema_name.clone(),
                 policy.table_name.clone(),
+                policy.kind.to_string(),
                 policy.command.clone(),
+                policy.roles.join(","),
                 policy.using_expr.clone(),
                 policy.check_expr.clone(),
             ],
-        ).await?;
-        Ok(())
+        ).await.map_err(Into::into)
     }
     
+    /// Remove a policy from the policy table of the given connection
+    ///
+    /// Fails if the policy does not exist, unless `if_exists` is set.
+    pub(crate) async fn drop_policy(
+        conn: &Connection,
+        name: &str,
+        schema_name: Option<&str>,
+        table_name: &str,
+        if_exists: bool,
+    ) -> Result<u64> {
+        let removed = conn.execute(
+            "DELETE FROM _rls_policies WHERE name = ? AND schema_name IS ? AND table_name = ?",
+            params![name, schema_name, table_name],
+        ).await?;
+        if removed == 0 && !if_exists {
+            return Err(policy_not_found(name, table_name));
+        }
+        Ok(removed)
+    }
+
+    /// Apply an ALTER POLICY change to a stored policy
+    ///
+    /// Each change is a single UPDATE, so a policy is never left partially
+    /// altered.
+    pub(crate) async fn alter_policy(
+        conn: &Connection,
+        name: &str,
+        schema_name: Option<&str>,
+        table_name: &str,
+        change: &AlterPolicyChange,
+    ) -> Result<u64> {
+        let updated = match change {
+            AlterPolicyChange::Rename(new_name) => conn.execute(
+                "UPDATE _rls_policies SET name = ?
+                 WHERE name = ? AND schema_name IS ? AND table_name = ?",
+                params![new_name.as_str(), name, schema_name, table_name],
+            ).await?,
+            AlterPolicyChange::Update { roles, using_expr, check_expr } => conn.execute(
+                "UPDATE _rls_policies
+                 SET roles = COALESCE(?, roles),
+                     using_expr = COALESCE(?, using_expr),
+                     check_expr = COALESCE(?, check_expr)
+                 WHERE name = ? AND schema_name IS ? AND table_name = ?",
+                params![
+                    roles.as_ref().map(|roles| roles.join(",")),
+                    using_expr.clone(),
+                    check_expr.clone(),
+                    name,
+                    schema_name,
+                    table_name
+                ],
+            ).await?,
+        };
+        if updated == 0 {
+            return Err(policy_not_found(name, table_name));
+        }
+        Ok(updated)
+    }
+
+    /// Insert a masking policy into the catalog of the given connection
+    ///
+    /// Fails if the column already has a masking policy, or the table a
+    /// masking policy of the same name.
+    pub(crate) async fn insert_masking_policy(conn: &Connection, policy: &MaskingPolicy) -> Result<u64> {
+        let mut rows = conn.query(
+            "SELECT name FROM _rls_masking_policies
+             WHERE table_name = ?1 AND (name = ?2 OR column_name = ?3 COLLATE NOCASE)",
+            params![policy.table_name.as_str(), policy.name.as_str(), policy.column_name.as_str()],
+        ).await?;
+        if let Some(row) = rows.next()? {
+            return Err(Error::Policy(format!(
+                "Masking policy {} for table {} conflicts with masking policy {}",
+                policy.name,
+                policy.table_name,
+                row.get::<String>(0)?
+            )));
+        }
+        conn.execute(
+            "INSERT INTO _rls_masking_policies (name, table_name, column_name, roles, mask_expr) VALUES (?, ?, ?, ?, ?)",
+            params![
+                policy.name.as_str(),
+                policy.table_name.as_str(),
+                policy.column_name.as_str(),
+                policy.roles.join(","),
+                policy.mask_expr.as_str()
+            ],
+        ).await.map_err(Into::into)
+    }
+
+    /// Remove a masking policy from the catalog of the given connection
+    ///
+    /// Fails if the policy does not exist, unless `if_exists` is set.
+    pub(crate) async fn drop_masking_policy(
+        conn: &Connection,
+        name: &str,
+        table_name: &str,
+        if_exists: bool,
+    ) -> Result<u64> {
+        let removed = conn.execute(
+            "DELETE FROM _rls_masking_policies WHERE name = ? AND table_name = ?",
+            params![name, table_name],
+        ).await?;
+        if removed == 0 && !if_exists {
+            return Err(Error::Policy(format!(
+                "Masking policy {} for table {} does not exist",
+                name, table_name
+            )));
+        }
+        Ok(removed)
+    }
+
+    /// Get the masking policies of a table
+    pub(crate) async fn masking_policies(conn: &Connection, table_name: &str) -> Result<Vec<MaskingPolicy>> {
+        let mut rows = conn.query(
+            "SELECT name, table_name, column_name, roles, mask_expr FROM _rls_masking_policies WHERE table_name = ?",
+            params![table_name],
+        ).await?;
+        let mut policies = Vec::new();
+        while let Some(row) = rows.next()? {
+            policies.push(MaskingPolicy {
+                name: row.get(0)?,
+                table_name: row.get(1)?,
+                column_name: row.get(2)?,
+                roles: row.get::<String>(3)?.split(',').map(str::to_string).collect(),
+                mask_expr: row.get(4)?,
+            });
+        }
+        Ok(policies)
+    }
+
+    /// Record a change to the row level security settings of a table
+    pub(crate) async fn set_row_level_security(
+        conn: &Connection,
+        table_name: &str,
+        action: RowLevelSecurityAction,
+    ) -> Result<u64> {
+        let (column, value) = match action {
+            RowLevelSecurityAction::Enable => ("enabled", 1),
+            RowLevelSecurityAction::Disable => ("enabled", 0),
+            RowLevelSecurityAction::Force => ("forced", 1),
+            RowLevelSecurityAction::NoForce => ("forced", 0),
+        };
+        conn.execute(
+            &format!(
+                "INSERT INTO _rls_tables (table_name, {column}) VALUES (?, ?)
+                 ON CONFLICT(table_name) DO UPDATE SET {column} = excluded.{column}"
+            ),
+            params![table_name, value],
+        ).await.map_err(Into::into)
+    }
+
+    /// Record the owner of a table, who is exempt from its policies unless
+    /// row level security is forced
+    pub(crate) async fn set_table_owner(conn: &Connection, table_name: &str, owner: &str) -> Result<u64> {
+        conn.execute(
+            "INSERT INTO _rls_tables (table_name, owner) VALUES (?, ?)
+             ON CONFLICT(table_name) DO UPDATE SET owner = excluded.owner",
+            params![table_name, owner],
+        ).await.map_err(Into::into)
+    }
+
+    /// Get the owner of a table, if one was set, and whether row level
+    /// security is forced on the owner
+    pub(crate) async fn table_owner(conn: &Connection, table_name: &str) -> Result<Option<(String, bool)>> {
+        let mut rows = conn.query(
+            "SELECT owner, forced FROM _rls_tables WHERE table_name = ? AND owner IS NOT NULL",
+            params![table_name],
+        ).await?;
+        match rows.next()? {
+            Some(row) => Ok(Some((row.get(0)?, row.get::<i64>(1)? != 0))),
+            None => Ok(None),
+        }
+    }
+
+    /// Check whether row level security applies to a table
+    ///
+    /// Tables that were never altered with `ALTER TABLE ... ROW LEVEL
+    /// SECURITY` keep the original behavior of being protected exactly when
+    /// they have policies.
+    pub(crate) async fn row_level_security_enabled(conn: &Connection, table_name: &str) -> Result<bool> {
+        let mut rows = conn.query(
+            "SELECT COALESCE(
+                (SELECT enabled FROM _rls_tables WHERE table_name = ?1),
+                EXISTS (SELECT 1 FROM _rls_policies WHERE table_name = ?1)
+            )",
+            params![table_name],
+        ).await?;
+        match rows.next()? {
+            Some(row) => Ok(row.get::<i64>(0)? != 0),
+            None => Ok(false),
+        }
+    }
+
     /// Get policies for a specific table
     pub async fn get_policies_for_table(&self, _schema_name: Option<&str>, _table_name: &str) -> Result<Vec<Policy>> {
         // Just for the prototype, return an empty Vec
         // This would normally query the database for matching policies
         Ok(Vec::new())
     }
-} 
\ No newline at end of file
+}
+
+/// Build the error reported when a policy to drop or alter does not exist
+fn policy_not_found(name: &str, table_name: &str) -> Error {
+    Error::Policy(format!(
+        "Policy {} for table {} does not exist",
+        name, table_name
+    ))
+}
@@ -0,0 +1,376 @@
+use crate::{
+    policy::{Policy, PolicyKind},
+    sql_parser, Error, Result,
+};
+use sqlparser::ast::{
+    BinaryOperator, Cte, Expr, Function, FunctionArg, FunctionArgExpr, Ident, ObjectName, Query, SelectItem,
+    Statement, TableAlias, TableFactor, TableWithJoins, Value, WildcardAdditionalOptions, With,
+};
+use sqlparser::dialect::SQLiteDialect;
+use sqlparser::parser::Parser;
+
+/// A column of a table, as reported by `PRAGMA table_info`
+#[derive(Debug, Clone)]
+pub struct TableColumn {
+    pub name: String,
+    /// The SQL text of the column's default value
+    pub default: Option<String>,
+}
+
+/// A query enforcing the WITH CHECK expressions of a table's policies
+///
+/// The rows a data-modifying statement writes are checked after it runs, and
+/// the existing rows it would remove or overwrite before. The query selects
+/// the first row that the policies reject, together with whether each
+/// policy's check passed for it, so the violated policy can be reported.
+#[derive(Debug, Clone)]
+pub struct PolicyCheck {
+    /// The table being modified
+    pub table: String,
+    /// The checked policies, in the order of the query's result columns
+    policies: Vec<(String, PolicyKind)>,
+    /// The query selecting a violating row, if any
+    pub statement: Statement,
+}
+
+impl PolicyCheck {
+    /// Check the rows produced by `rows` against the policies of `table`
+    ///
+    /// `rows` must produce rows shaped like the table, which the check
+    /// expressions see as `alias`. Each policy checks its WITH CHECK
+    /// expression, falling back to USING as in Postgres.
+    pub fn new(table: &str, alias: Ident, rows: Query, policies: &[Policy]) -> Result<Self> {
+        let condition = sql_parser::combine_policy_checks(policies)?;
+        Self::build(table, alias, rows, policies, condition, |policy| {
+            policy.check_expr.as_ref().or(policy.using_expr.as_ref())
+        })
+    }
+
+    /// Check existing rows produced by `rows` against the USING expressions
+    /// of the policies of `table`
+    ///
+    /// Used where a statement would modify rows it was never allowed to see,
+    /// such as the existing row an upsert updates.
+    pub fn using(table: &str, alias: Ident, rows: Query, policies: &[Policy]) -> Result<Self> {
+        let condition = sql_parser::combine_policy_expressions(policies)?;
+        Self::build(table, alias, rows, policies, condition, |policy| policy.using_expr.as_ref())
+    }
+
+    fn build(
+        table: &str,
+        alias: Ident,
+        rows: Query,
+        policies: &[Policy],
+        condition: Expr,
+        expression: impl Fn(&Policy) -> Option<&String>,
+    ) -> Result<Self> {
+        let mut checked = Vec::new();
+        let mut projection = vec![SelectItem::UnnamedExpr(Expr::Value(Value::Number(
+            "1".to_string(),
+            false,
+        )))];
+        for policy in policies {
+            let Some(expr) = expression(policy) else {
+                continue;
+            };
+            let expr = sql_parser::parse_policy_expression(expr)?;
+            projection.push(SelectItem::UnnamedExpr(Expr::IsTrue(Box::new(Expr::Nested(
+                Box::new(expr),
+            )))));
+            checked.push((policy.name.clone(), policy.kind));
+        }
+
+        let relation = TableFactor::Derived {
+            lateral: false,
+            subquery: Box::new(rows),
+            alias: Some(TableAlias {
+                name: alias,
+                columns: Vec::new(),
+            }),
+        };
+        let mut query = sql_parser::select_query(
+            projection,
+            vec![TableWithJoins {
+                relation,
+                joins: Vec::new(),
+            }],
+            Some(Expr::IsNotTrue(Box::new(Expr::Nested(Box::new(condition))))),
+        );
+        query.limit = Some(Expr::Value(Value::Number("1".to_string(), false)));
+
+        Ok(Self {
+            table: table.to_string(),
+            policies: checked,
+            statement: Statement::Query(Box::new(query)),
+        })
+    }
+
+    /// Build the error for a row returned by the check query
+    ///
+    /// A failed restrictive policy is reported by name. When no permissive
+    /// policy allowed the row, the permissive policy is named only if there
+    /// is exactly one, as no single policy is to blame otherwise.
+    pub fn violation(&self, row: &libsql::Row) -> Result<Error> {
+        let mut permissive = Vec::new();
+        let mut failed_restrictive = None;
+        for (index, (name, kind)) in self.policies.iter().enumerate() {
+            let passed: bool = row.get(index as i32 + 1)?;
+            match kind {
+                PolicyKind::Permissive => permissive.push((name, passed)),
+                PolicyKind::Restrictive if !passed && failed_restrictive.is_none() => {
+                    failed_restrictive = Some(name)
+                }
+                PolicyKind::Restrictive => {}
+            }
+        }
+
+        let policy = if !permissive.iter().any(|(_, passed)| *passed) {
+            match permissive.as_slice() {
+                [(name, _)] => Some((*name).clone()),
+                _ => None,
+            }
+        } else {
+            failed_restrictive.cloned()
+        };
+
+        Ok(Error::PolicyViolation {
+            policy,
+            table: self.table.clone(),
+        })
+    }
+}
+
+/// Build the rows an INSERT proposes, shaped like the target table
+///
+/// The source rows are read through a CTE so that VALUES and SELECT sources
+/// are handled alike, and columns the INSERT leaves out take their default.
+///
+/// ```sql
+/// WITH rls_source(a, b) AS (<source>)
+/// SELECT a AS a, b AS b, <default> AS c FROM rls_source
+/// ```
+pub fn insert_rows(table_columns: &[TableColumn], columns: &[Ident], source: Query) -> Result<Query> {
+    let columns: Vec<Ident> = if columns.is_empty() {
+        table_columns.iter().map(|column| Ident::with_quote('"', &column.name)).collect()
+    } else {
+        columns.to_vec()
+    };
+
+    let mut projection = Vec::with_capacity(table_columns.len());
+    for column in table_columns {
+        let value = if let Some(ident) = columns
+            .iter()
+            .find(|ident| ident.value.eq_ignore_ascii_case(&column.name))
+        {
+            Expr::Identifier(ident.clone())
+        } else if let Some(default) = &column.default {
+            parse_default(default)?
+        } else {
+            Expr::Value(Value::Null)
+        };
+        projection.push(SelectItem::ExprWithAlias {
+            expr: value,
+            alias: Ident::with_quote('"', &column.name),
+        });
+    }
+
+    let source_name = Ident::new("rls_source");
+    let mut rows = sql_parser::select_query(
+        projection,
+        vec![TableWithJoins {
+            relation: TableFactor::Table {
+                name: ObjectName(vec![source_name.clone()]),
+                alias: None,
+                args: None,
+                with_hints: Vec::new(),
+            },
+            joins: Vec::new(),
+        }],
+        None,
+    );
+    rows.with = Some(With {
+        recursive: false,
+        cte_tables: vec![Cte {
+            alias: TableAlias {
+                name: source_name,
+                columns,
+            },
+            query: Box::new(source),
+            from: None,
+        }],
+    });
+
+    Ok(rows)
+}
+
+/// The temporary table recording the rows a statement writes, so that they
+/// can be checked after it runs
+pub const WRITTEN_TABLE: &str = "_rls_written";
+
+/// Build the value identifying a row of a table, seen as `qualifier`, from
+/// the columns of its `key`: `rowid`, or the primary key of a `WITHOUT
+/// ROWID` table
+///
+/// A key of several columns is joined into one value, as in
+/// `quote(t.a) || ',' || quote(t.b)`, which `quote` keeps unambiguous.
+pub fn row_key(qualifier: &Ident, key: &[String]) -> Expr {
+    let column = |name: &String| Expr::CompoundIdentifier(vec![qualifier.clone(), Ident::with_quote('"', name)]);
+    if let [name] = key {
+        return column(name);
+    }
+    key.iter()
+        .map(|name| {
+            Expr::Function(Function {
+                name: ObjectName(vec![Ident::new("quote")]),
+                args: vec![FunctionArg::Unnamed(FunctionArgExpr::Expr(column(name)))],
+                over: None,
+                distinct: false,
+                special: false,
+                order_by: Vec::new(),
+            })
+        })
+        .reduce(|joined, quoted| Expr::BinaryOp {
+            left: Box::new(Expr::BinaryOp {
+                left: Box::new(joined),
+                op: BinaryOperator::StringConcat,
+                right: Box::new(Expr::Value(Value::SingleQuotedString(",".to_string()))),
+            }),
+            op: BinaryOperator::StringConcat,
+            right: Box::new(quoted),
+        })
+        .expect("a table has at least one key column")
+}
+
+/// Build the rows of `table` a statement wrote, as recorded in
+/// `WRITTEN_TABLE` under their `row_key`, optionally only those written by
+/// one command
+///
+/// An upsert records the rows it inserts under `INSERT` and those it
+/// updates under `UPDATE`.
+///
+/// ```sql
+/// SELECT t.* FROM t WHERE t.rowid IN (
+///     SELECT row_key FROM temp._rls_written [WHERE command = 'INSERT']
+/// )
+/// ```
+pub fn written_rows(table: &Ident, key: &[String], command: Option<&str>) -> Query {
+    let written = sql_parser::select_query(
+        vec![SelectItem::UnnamedExpr(Expr::Identifier(Ident::new("row_key")))],
+        vec![TableWithJoins {
+            relation: TableFactor::Table {
+                name: ObjectName(vec![Ident::new("temp"), Ident::new(WRITTEN_TABLE)]),
+                alias: None,
+                args: None,
+                with_hints: Vec::new(),
+            },
+            joins: Vec::new(),
+        }],
+        command.map(|command| Expr::BinaryOp {
+            left: Box::new(Expr::Identifier(Ident::new("command"))),
+            op: BinaryOperator::Eq,
+            right: Box::new(Expr::Value(Value::SingleQuotedString(command.to_string()))),
+        }),
+    );
+    sql_parser::select_query(
+        vec![SelectItem::QualifiedWildcard(
+            ObjectName(vec![table.clone()]),
+            WildcardAdditionalOptions::default(),
+        )],
+        vec![table_rows(table)],
+        Some(Expr::InSubquery {
+            expr: Box::new(row_key(table, key)),
+            subquery: Box::new(written),
+            negated: false,
+        }),
+    )
+}
+
+/// Build the existing rows an upsert conflicts with
+///
+/// A row conflicts when any of the unique `keys` matches a proposed row:
+///
+/// ```sql
+/// SELECT t.* FROM t WHERE EXISTS (
+///     SELECT 1 FROM (<proposed>) AS excluded WHERE t.k = excluded.k
+/// )
+/// ```
+pub fn conflicting_rows(table: &Ident, keys: &[Vec<String>], proposed: Query) -> Query {
+    let exists = sql_parser::select_query(
+        vec![SelectItem::UnnamedExpr(Expr::Value(Value::Number("1".to_string(), false)))],
+        vec![excluded_rows(proposed)],
+        Some(key_match(table, keys)),
+    );
+    sql_parser::select_query(
+        vec![SelectItem::QualifiedWildcard(
+            ObjectName(vec![table.clone()]),
+            WildcardAdditionalOptions::default(),
+        )],
+        vec![table_rows(table)],
+        Some(Expr::Exists {
+            subquery: Box::new(exists),
+            negated: false,
+        }),
+    )
+}
+
+/// Match the rows of `table` against `excluded` on any of the unique `keys`
+fn key_match(table: &Ident, keys: &[Vec<String>]) -> Expr {
+    keys.iter()
+        .filter(|key| !key.is_empty())
+        .map(|key| {
+            let mut columns = key.iter().map(|column| {
+                let column = Ident::with_quote('"', column);
+                Expr::BinaryOp {
+                    left: Box::new(Expr::CompoundIdentifier(vec![table.clone(), column.clone()])),
+                    op: BinaryOperator::Eq,
+                    right: Box::new(Expr::CompoundIdentifier(vec![Ident::new("excluded"), column])),
+                }
+            });
+            let first = columns.next().expect("keys are not empty");
+            let matched = columns.fold(first, |left, right| Expr::BinaryOp {
+                left: Box::new(left),
+                op: BinaryOperator::And,
+                right: Box::new(right),
+            });
+            Expr::Nested(Box::new(matched))
+        })
+        .reduce(|left, right| Expr::BinaryOp {
+            left: Box::new(left),
+            op: BinaryOperator::Or,
+            right: Box::new(right),
+        })
+        .unwrap_or(Expr::Value(Value::Boolean(false)))
+}
+
+fn table_rows(table: &Ident) -> TableWithJoins {
+    TableWithJoins {
+        relation: TableFactor::Table {
+            name: ObjectName(vec![table.clone()]),
+            alias: None,
+            args: None,
+            with_hints: Vec::new(),
+        },
+        joins: Vec::new(),
+    }
+}
+
+fn excluded_rows(proposed: Query) -> TableWithJoins {
+    TableWithJoins {
+        relation: TableFactor::Derived {
+            lateral: false,
+            subquery: Box::new(proposed),
+            alias: Some(TableAlias {
+                name: Ident::new("excluded"),
+                columns: Vec::new(),
+            }),
+        },
+        joins: Vec::new(),
+    }
+}
+
+/// Parse the default value of a column
+fn parse_default(default: &str) -> Result<Expr> {
+    let dialect = SQLiteDialect {};
+    let expr = Parser::new(&dialect).try_with_sql(default)?.parse_expr()?;
+    Ok(Expr::Nested(Box::new(expr)))
+}
@@ -0,0 +1,210 @@
+use crate::{Error, Result};
+use libsql::{Connection, params};
+use std::fmt;
+use std::str::FromStr;
+
+/// A table privilege granted with `GRANT privilege ON table TO role`
+///
+/// Privileges decide which statements a role may run against a table at
+/// all, before its policies decide which rows those statements see.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Privilege {
+    Select,
+    Insert,
+    Update,
+    Delete,
+}
+
+impl Privilege {
+    /// The privileges granted by `GRANT ALL [PRIVILEGES]`
+    pub const ALL: [Privilege; 4] = [Privilege::Select, Privilege::Insert, Privilege::Update, Privilege::Delete];
+}
+
+impl fmt::Display for Privilege {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Privilege::Select => f.write_str("SELECT"),
+            Privilege::Insert => f.write_str("INSERT"),
+            Privilege::Update => f.write_str("UPDATE"),
+            Privilege::Delete => f.write_str("DELETE"),
+        }
+    }
+}
+
+impl FromStr for Privilege {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Privilege::ALL
+            .into_iter()
+            .find(|privilege| s.eq_ignore_ascii_case(&privilege.to_string()))
+            .ok_or_else(|| Error::Policy(format!("Unknown privilege: {}", s)))
+    }
+}
+
+/// A privilege named by GRANT or REVOKE, along with the columns it is
+/// limited to, as in `SELECT (id, name)`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TablePrivilege {
+    pub privilege: Privilege,
+    /// The columns the privilege covers, `None` for the whole table
+    pub columns: Option<Vec<String>>,
+}
+
+/// The access a session has to a table under one privilege
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Access {
+    /// The privilege covers the whole table
+    Table,
+    /// The privilege only covers these columns
+    Columns(Vec<String>),
+    /// The session lacks the privilege
+    Denied,
+}
+
+/// Grant privileges on a table to a role
+///
+/// The first GRANT or REVOKE naming a table puts it under privilege
+/// checks, after which only the roles it was granted to can use it.
+pub(crate) async fn grant_privileges(
+    conn: &Connection,
+    table_name: &str,
+    role: &str,
+    privileges: &[TablePrivilege],
+) -> Result<u64> {
+    manage_privileges(conn, table_name).await?;
+    let mut granted = 0;
+    for TablePrivilege { privilege, columns } in privileges {
+        match columns {
+            Some(columns) => {
+                for column in columns {
+                    granted += conn.execute(
+                        "INSERT INTO _rls_column_grants (table_name, role, privilege, column_name)
+                         VALUES (?, ?, ?, ?) ON CONFLICT DO NOTHING",
+                        params![table_name, role, privilege.to_string(), column.as_str()],
+                    ).await?;
+                }
+            }
+            None => {
+                granted += conn.execute(
+                    "INSERT INTO _rls_grants (table_name, role, privilege) VALUES (?, ?, ?) ON CONFLICT DO NOTHING",
+                    params![table_name, role, privilege.to_string()],
+                ).await?;
+            }
+        }
+    }
+    Ok(granted)
+}
+
+/// Revoke privileges on a table from a role
+///
+/// As in Postgres, revoking a privilege on the whole table also revokes it
+/// on each of the table's columns.
+pub(crate) async fn revoke_privileges(
+    conn: &Connection,
+    table_name: &str,
+    role: &str,
+    privileges: &[TablePrivilege],
+) -> Result<u64> {
+    manage_privileges(conn, table_name).await?;
+    let mut revoked = 0;
+    for TablePrivilege { privilege, columns } in privileges {
+        match columns {
+            Some(columns) => {
+                for column in columns {
+                    revoked += conn.execute(
+                        "DELETE FROM _rls_column_grants
+                         WHERE table_name = ? AND role = ? AND privilege = ? AND column_name = ?",
+                        params![table_name, role, privilege.to_string(), column.as_str()],
+                    ).await?;
+                }
+            }
+            None => {
+                for catalog in ["_rls_grants", "_rls_column_grants"] {
+                    revoked += conn.execute(
+                        &format!("DELETE FROM {catalog} WHERE table_name = ? AND role = ? AND privilege = ?"),
+                        params![table_name, role, privilege.to_string()],
+                    ).await?;
+                }
+            }
+        }
+    }
+    Ok(revoked)
+}
+
+/// Get the access a session with the given effective roles has to a table
+/// under a privilege
+///
+/// Tables never named by a GRANT or REVOKE keep the original behavior of
+/// being usable by every role. Otherwise, as in Postgres, the table's
+/// owner holds every privilege and other roles need a grant to one of
+/// their effective roles, which include `PUBLIC`. Without a grant on the
+/// whole table, grants on some of its columns give access to those.
+pub(crate) async fn table_access(
+    conn: &Connection,
+    table_name: &str,
+    roles: &[String],
+    privilege: Privilege,
+) -> Result<Access> {
+    let mut rows = conn.query(
+        "SELECT owner FROM _rls_tables WHERE table_name = ? AND privileges_managed",
+        params![table_name],
+    ).await?;
+    let owner: Option<String> = match rows.next()? {
+        Some(row) => row.get(0)?,
+        None => return Ok(Access::Table),
+    };
+    if owner.is_some_and(|owner| roles.contains(&owner)) {
+        return Ok(Access::Table);
+    }
+
+    let mut rows = conn.query(
+        "SELECT role FROM _rls_grants WHERE table_name = ? AND privilege = ?",
+        params![table_name, privilege.to_string()],
+    ).await?;
+    while let Some(row) = rows.next()? {
+        if roles.contains(&row.get::<String>(0)?) {
+            return Ok(Access::Table);
+        }
+    }
+
+    let mut rows = conn.query(
+        "SELECT DISTINCT role, column_name FROM _rls_column_grants WHERE table_name = ? AND privilege = ?",
+        params![table_name, privilege.to_string()],
+    ).await?;
+    let mut columns = Vec::new();
+    while let Some(row) = rows.next()? {
+        let column: String = row.get(1)?;
+        if roles.contains(&row.get::<String>(0)?) && !columns.contains(&column) {
+            columns.push(column);
+        }
+    }
+    Ok(if columns.is_empty() { Access::Denied } else { Access::Columns(columns) })
+}
+
+/// Check whether a session with the given effective roles may drop or
+/// index a table
+///
+/// As with `table_access`, tables never named by a GRANT or REVOKE are
+/// open to every role. Otherwise, as in Postgres, only the table's owner
+/// may, whatever privileges other roles hold on it.
+pub(crate) async fn may_change_table(conn: &Connection, table_name: &str, roles: &[String]) -> Result<bool> {
+    let mut rows = conn.query(
+        "SELECT owner FROM _rls_tables WHERE table_name = ? AND privileges_managed",
+        params![table_name],
+    ).await?;
+    match rows.next()? {
+        Some(row) => Ok(row.get::<Option<String>>(0)?.is_some_and(|owner| roles.contains(&owner))),
+        None => Ok(true),
+    }
+}
+
+/// Record that privileges are checked for a table
+async fn manage_privileges(conn: &Connection, table_name: &str) -> Result<()> {
+    conn.execute(
+        "INSERT INTO _rls_tables (table_name, privileges_managed) VALUES (?, 1)
+         ON CONFLICT(table_name) DO UPDATE SET privileges_managed = 1",
+        params![table_name],
+    ).await?;
+    Ok(())
+}
@@ -0,0 +1,681 @@
+use crate::{policy::{MaskingPolicy, Policy, PolicyKind}, privilege::{Privilege, TablePrivilege}, role::PUBLIC_ROLE, sql_parser, Error, Result};
+use sqlparser::ast::Ident;
+use sqlparser::dialect::SQLiteDialect;
+use sqlparser::keywords::Keyword;
+use sqlparser::parser::{Parser, ParserError};
+use sqlparser::tokenizer::{Location, Token, Tokenizer};
+
+/// A statement that is handled by the RLS layer instead of being passed
+/// through to libSQL
+#[derive(Debug, Clone)]
+pub enum RlsStatement {
+    /// `CREATE POLICY name ON table ...`
+    CreatePolicy(Policy),
+    /// `DROP POLICY [IF EXISTS] name ON table`
+    DropPolicy {
+        name: String,
+        schema_name: Option<String>,
+        table_name: String,
+        if_exists: bool,
+    },
+    /// `ALTER POLICY name ON table ...`
+    AlterPolicy {
+        name: String,
+        schema_name: Option<String>,
+        table_name: String,
+        change: AlterPolicyChange,
+    },
+    /// `CREATE MASKING POLICY name ON table (column) [TO roles] USING (expr)`
+    CreateMaskingPolicy(MaskingPolicy),
+    /// `DROP MASKING POLICY [IF EXISTS] name ON table`
+    DropMaskingPolicy {
+        name: String,
+        table_name: String,
+        if_exists: bool,
+    },
+    /// `ALTER TABLE table { ENABLE | DISABLE | [NO] FORCE } ROW LEVEL SECURITY`
+    AlterTableRowLevelSecurity {
+        table_name: String,
+        action: RowLevelSecurityAction,
+    },
+    /// `ALTER TABLE table OWNER TO role`
+    AlterTableOwner { table_name: String, owner: String },
+    /// `CREATE ROLE name [ [WITH] { BYPASSRLS | NOBYPASSRLS } ]`
+    CreateRole { name: String, bypass: bool },
+    /// `ALTER ROLE name [WITH] { BYPASSRLS | NOBYPASSRLS }`
+    AlterRole { name: String, bypass: bool },
+    /// `DROP ROLE [IF EXISTS] name`
+    DropRole { name: String, if_exists: bool },
+    /// `GRANT role [, ...] TO member [, ...]`
+    GrantRole {
+        roles: Vec<String>,
+        members: Vec<String>,
+    },
+    /// `REVOKE role [, ...] FROM member [, ...]`
+    RevokeRole {
+        roles: Vec<String>,
+        members: Vec<String>,
+    },
+    /// `GRANT privilege [, ...] ON [TABLE] table [, ...] TO role [, ...]`
+    GrantPrivileges {
+        privileges: Vec<TablePrivilege>,
+        tables: Vec<String>,
+        roles: Vec<String>,
+    },
+    /// `REVOKE privilege [, ...] ON [TABLE] table [, ...] FROM role [, ...]`
+    RevokePrivileges {
+        privileges: Vec<TablePrivilege>,
+        tables: Vec<String>,
+        roles: Vec<String>,
+    },
+}
+
+/// The change made to a policy by ALTER POLICY
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AlterPolicyChange {
+    /// `RENAME TO new_name`
+    Rename(String),
+    /// `[TO roles] [USING (...)] [WITH CHECK (...)]`, where omitted clauses
+    /// keep their current value
+    Update {
+        roles: Option<Vec<String>>,
+        using_expr: Option<String>,
+        check_expr: Option<String>,
+    },
+}
+
+/// A change to the row level security settings of a table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RowLevelSecurityAction {
+    /// Apply the table's policies, denying all rows if it has none
+    Enable,
+    /// Stop applying the table's policies
+    Disable,
+    /// Apply the table's policies to its owner as well
+    Force,
+    /// Exempt the table's owner from its policies again
+    NoForce,
+}
+
+/// Parse an RLS statement
+///
+/// Returns `Ok(None)` when the SQL is not an RLS statement, so that the
+/// caller can fall back to regular SQL handling. Once the leading keywords
+/// identify an RLS statement, any malformed input is reported as an
+/// `Error::Policy` including the line and column of the offending token.
+pub fn parse_rls_statement(sql: &str) -> Result<Option<RlsStatement>> {
+    let dialect = SQLiteDialect {};
+    let tokens = match Tokenizer::new(&dialect, sql).tokenize_with_location() {
+        Ok(tokens) => tokens,
+        Err(e) if ["CREATE", "DROP", "ALTER"].iter().any(|verb| starts_with_words(sql, &[*verb, "POLICY"])) => {
+            return Err(Error::Policy(e.to_string()))
+        }
+        Err(_) => return Ok(None),
+    };
+    let mut parser = Parser::new(&dialect).with_tokens_with_locations(tokens);
+
+    let statement = match parser.parse_one_of_keywords(&[
+        Keyword::CREATE,
+        Keyword::DROP,
+        Keyword::ALTER,
+        Keyword::GRANT,
+        Keyword::REVOKE,
+    ]) {
+        Some(Keyword::CREATE) if parse_word(&mut parser, "POLICY") => {
+            RlsStatement::CreatePolicy(parse_create_policy_body(&mut parser)?)
+        }
+        Some(Keyword::DROP) if parse_word(&mut parser, "POLICY") => parse_drop_policy_body(&mut parser)?,
+        Some(Keyword::ALTER) if parse_word(&mut parser, "POLICY") => parse_alter_policy_body(&mut parser)?,
+        Some(Keyword::ALTER) if parser.parse_keyword(Keyword::TABLE) => return parse_alter_table(&mut parser),
+        Some(Keyword::CREATE) if parse_word(&mut parser, "MASKING") => {
+            expect_word(&mut parser, "POLICY")?;
+            RlsStatement::CreateMaskingPolicy(parse_create_masking_policy_body(&mut parser)?)
+        }
+        Some(Keyword::DROP) if parse_word(&mut parser, "MASKING") => {
+            expect_word(&mut parser, "POLICY")?;
+            let if_exists = parser.parse_keywords(&[Keyword::IF, Keyword::EXISTS]);
+            let name = parse_with(&mut parser, Parser::parse_identifier)?.value;
+            if !parser.parse_keyword(Keyword::ON) {
+                return expected(&parser, "ON after policy name");
+            }
+            RlsStatement::DropMaskingPolicy {
+                name,
+                table_name: parse_table_name(&mut parser)?.1,
+                if_exists,
+            }
+        }
+        Some(Keyword::CREATE) if parser.parse_keyword(Keyword::ROLE) => {
+            let name = parse_with(&mut parser, Parser::parse_identifier)?.value;
+            RlsStatement::CreateRole {
+                name,
+                bypass: parse_bypass_option(&mut parser)?.unwrap_or(false),
+            }
+        }
+        Some(Keyword::ALTER) if parser.parse_keyword(Keyword::ROLE) => {
+            let name = parse_with(&mut parser, Parser::parse_identifier)?.value;
+            match parse_bypass_option(&mut parser)? {
+                Some(bypass) => RlsStatement::AlterRole { name, bypass },
+                None => return expected(&parser, "BYPASSRLS or NOBYPASSRLS after role name"),
+            }
+        }
+        Some(Keyword::DROP) if parser.parse_keyword(Keyword::ROLE) => {
+            let if_exists = parser.parse_keywords(&[Keyword::IF, Keyword::EXISTS]);
+            RlsStatement::DropRole {
+                name: parse_with(&mut parser, Parser::parse_identifier)?.value,
+                if_exists,
+            }
+        }
+        Some(keyword @ (Keyword::GRANT | Keyword::REVOKE)) => {
+            let grant = keyword == Keyword::GRANT;
+            match parse_privileges(&mut parser)? {
+                Some(privileges) => parse_table_privileges(&mut parser, privileges, grant)?,
+                None => return parse_role_membership(&mut parser, grant),
+            }
+        }
+        _ => return Ok(None),
+    };
+    expect_end(&mut parser)?;
+
+    Ok(Some(statement))
+}
+
+/// Parse a CREATE POLICY statement into a `Policy`
+pub fn parse_create_policy(sql: &str) -> Result<Policy> {
+    match parse_rls_statement(sql)? {
+        Some(RlsStatement::CreatePolicy(policy)) => Ok(policy),
+        _ => Err(Error::Policy("Expected a CREATE POLICY statement".to_string())),
+    }
+}
+
+/// Parse everything following `CREATE POLICY`
+///
+/// ```sql
+/// CREATE POLICY name ON table_name
+///     [ AS { PERMISSIVE | RESTRICTIVE } ]
+///     [ FOR { ALL | SELECT | INSERT | UPDATE | DELETE } ]
+///     [ TO role_name [, ...] ]
+///     [ USING ( using_expression ) ]
+///     [ WITH CHECK ( check_expression ) ]
+/// ```
+fn parse_create_policy_body(parser: &mut Parser) -> Result<Policy> {
+    let name = parse_with(parser, Parser::parse_identifier)?.value;
+
+    if !parser.parse_keyword(Keyword::ON) {
+        return expected(parser, "ON after policy name");
+    }
+    let (schema_name, table_name) = parse_table_name(parser)?;
+
+    let kind = if parser.parse_keyword(Keyword::AS) {
+        if parse_word(parser, "PERMISSIVE") {
+            PolicyKind::Permissive
+        } else if parse_word(parser, "RESTRICTIVE") {
+            PolicyKind::Restrictive
+        } else {
+            return expected(parser, "PERMISSIVE or RESTRICTIVE after AS");
+        }
+    } else {
+        PolicyKind::Permissive
+    };
+
+    let command = if parser.parse_keyword(Keyword::FOR) {
+        match parser.parse_one_of_keywords(&[
+            Keyword::ALL,
+            Keyword::SELECT,
+            Keyword::INSERT,
+            Keyword::UPDATE,
+            Keyword::DELETE,
+        ]) {
+            Some(keyword) => format!("{:?}", keyword),
+            None => return expected(parser, "ALL, SELECT, INSERT, UPDATE or DELETE after FOR"),
+        }
+    } else {
+        "ALL".to_string()
+    };
+
+    let roles = if parser.parse_keyword(Keyword::TO) {
+        parse_roles(parser)?
+    } else {
+        vec![PUBLIC_ROLE.to_string()]
+    };
+    let (using_expr, check_expr) = parse_policy_expressions(parser)?;
+
+    Ok(Policy {
+        name,
+        schema_name,
+        table_name,
+        kind,
+        command,
+        roles,
+        using_expr,
+        check_expr,
+    })
+}
+
+/// Parse everything following `DROP POLICY`
+///
+/// ```sql
+/// DROP POLICY [ IF EXISTS ] name ON table_name
+/// ```
+fn parse_drop_policy_body(parser: &mut Parser) -> Result<RlsStatement> {
+    let if_exists = parser.parse_keywords(&[Keyword::IF, Keyword::EXISTS]);
+    let name = parse_with(parser, Parser::parse_identifier)?.value;
+
+    if !parser.parse_keyword(Keyword::ON) {
+        return expected(parser, "ON after policy name");
+    }
+    let (schema_name, table_name) = parse_table_name(parser)?;
+
+    Ok(RlsStatement::DropPolicy {
+        name,
+        schema_name,
+        table_name,
+        if_exists,
+    })
+}
+
+/// Parse everything following `ALTER POLICY`
+///
+/// ```sql
+/// ALTER POLICY name ON table_name RENAME TO new_name
+///
+/// ALTER POLICY name ON table_name
+///     [ TO role_name [, ...] ]
+///     [ USING ( using_expression ) ]
+///     [ WITH CHECK ( check_expression ) ]
+/// ```
+fn parse_alter_policy_body(parser: &mut Parser) -> Result<RlsStatement> {
+    let name = parse_with(parser, Parser::parse_identifier)?.value;
+
+    if !parser.parse_keyword(Keyword::ON) {
+        return expected(parser, "ON after policy name");
+    }
+    let (schema_name, table_name) = parse_table_name(parser)?;
+
+    let change = if parser.parse_keyword(Keyword::RENAME) {
+        if !parser.parse_keyword(Keyword::TO) {
+            return expected(parser, "TO after RENAME");
+        }
+        AlterPolicyChange::Rename(parse_with(parser, Parser::parse_identifier)?.value)
+    } else {
+        let roles = if parser.parse_keyword(Keyword::TO) {
+            Some(parse_roles(parser)?)
+        } else {
+            None
+        };
+        let (using_expr, check_expr) = parse_policy_expressions(parser)?;
+        if roles.is_none() && using_expr.is_none() && check_expr.is_none() {
+            return expected(parser, "RENAME TO, TO, USING or WITH CHECK after table name");
+        }
+        AlterPolicyChange::Update {
+            roles,
+            using_expr,
+            check_expr,
+        }
+    };
+
+    Ok(RlsStatement::AlterPolicy {
+        name,
+        schema_name,
+        table_name,
+        change,
+    })
+}
+
+/// Parse everything following `CREATE MASKING POLICY`
+///
+/// ```sql
+/// CREATE MASKING POLICY name ON table_name ( column_name )
+///     [ TO role_name [, ...] ]
+///     USING ( mask_expression )
+/// ```
+fn parse_create_masking_policy_body(parser: &mut Parser) -> Result<MaskingPolicy> {
+    let name = parse_with(parser, Parser::parse_identifier)?.value;
+
+    if !parser.parse_keyword(Keyword::ON) {
+        return expected(parser, "ON after policy name");
+    }
+    // Masks are looked up by table name alone, like policies
+    let (_, table_name) = parse_table_name(parser)?;
+
+    if !parser.consume_token(&Token::LParen) {
+        return expected(parser, "( after table name");
+    }
+    let column_name = parse_with(parser, Parser::parse_identifier)?.value;
+    if !parser.consume_token(&Token::RParen) {
+        return expected(parser, ")");
+    }
+
+    let roles = if parser.parse_keyword(Keyword::TO) {
+        parse_roles(parser)?
+    } else {
+        vec![PUBLIC_ROLE.to_string()]
+    };
+
+    if !parser.parse_keyword(Keyword::USING) {
+        return expected(parser, "USING after column name or roles");
+    }
+    let mask_expr = parse_parenthesized_expr(parser)?;
+
+    Ok(MaskingPolicy {
+        name,
+        table_name,
+        column_name,
+        roles,
+        mask_expr,
+    })
+}
+
+/// Parse everything following `GRANT` or `REVOKE` if it changes role
+/// membership
+///
+/// ```sql
+/// GRANT role_name [, ...] TO member_name [, ...]
+/// REVOKE role_name [, ...] FROM member_name [, ...]
+/// ```
+///
+/// Any other GRANT or REVOKE statement yields `None`.
+fn parse_role_membership(parser: &mut Parser, grant: bool) -> Result<Option<RlsStatement>> {
+    let Ok(roles) = parser.parse_comma_separated(Parser::parse_identifier) else {
+        return Ok(None);
+    };
+    let separator = if grant { Keyword::TO } else { Keyword::FROM };
+    if !parser.parse_keyword(separator) {
+        return Ok(None);
+    }
+    let members = parse_roles(parser)?;
+    expect_end(parser)?;
+
+    let roles = roles.into_iter().map(|role| role.value).collect();
+    Ok(Some(if grant {
+        RlsStatement::GrantRole { roles, members }
+    } else {
+        RlsStatement::RevokeRole { roles, members }
+    }))
+}
+
+/// Parse the privilege list of a GRANT or REVOKE statement
+///
+/// ```sql
+/// { SELECT [ ( column_name [, ...] ) ] | INSERT | UPDATE | DELETE } [, ...]
+/// ALL [ PRIVILEGES ]
+/// ```
+///
+/// Returns `None` when the statement doesn't start with a privilege, so
+/// that it can be parsed as a role membership change instead.
+fn parse_privileges(parser: &mut Parser) -> Result<Option<Vec<TablePrivilege>>> {
+    if parser.parse_keyword(Keyword::ALL) {
+        let _ = parser.parse_keyword(Keyword::PRIVILEGES);
+        return Ok(Some(
+            Privilege::ALL
+                .into_iter()
+                .map(|privilege| TablePrivilege { privilege, columns: None })
+                .collect(),
+        ));
+    }
+
+    let mut privileges = Vec::new();
+    loop {
+        let privilege = match parser.parse_one_of_keywords(&[
+            Keyword::SELECT,
+            Keyword::INSERT,
+            Keyword::UPDATE,
+            Keyword::DELETE,
+        ]) {
+            Some(Keyword::SELECT) => Privilege::Select,
+            Some(Keyword::INSERT) => Privilege::Insert,
+            Some(Keyword::UPDATE) => Privilege::Update,
+            Some(_) => Privilege::Delete,
+            None if privileges.is_empty() => return Ok(None),
+            None => return expected(parser, "SELECT, INSERT, UPDATE or DELETE after ,"),
+        };
+        // Only reads are limited to columns, by rewriting the query
+        let columns = if privilege == Privilege::Select && parser.consume_token(&Token::LParen) {
+            let columns = parse_with(parser, |p| p.parse_comma_separated(Parser::parse_identifier))?;
+            if !parser.consume_token(&Token::RParen) {
+                return expected(parser, ")");
+            }
+            Some(columns.into_iter().map(|column| column.value).collect())
+        } else {
+            None
+        };
+        let privilege = TablePrivilege { privilege, columns };
+        if !privileges.contains(&privilege) {
+            privileges.push(privilege);
+        }
+        if !parser.consume_token(&Token::Comma) {
+            return Ok(Some(privileges));
+        }
+    }
+}
+
+/// Parse everything following the privilege list of a GRANT or REVOKE
+///
+/// ```sql
+/// GRANT privileges ON [ TABLE ] table_name [, ...] TO role_name [, ...]
+/// REVOKE privileges ON [ TABLE ] table_name [, ...] FROM role_name [, ...]
+/// ```
+fn parse_table_privileges(parser: &mut Parser, privileges: Vec<TablePrivilege>, grant: bool) -> Result<RlsStatement> {
+    if !parser.parse_keyword(Keyword::ON) {
+        return expected(parser, "ON after privileges");
+    }
+    let _ = parser.parse_keyword(Keyword::TABLE);
+    let mut tables = Vec::new();
+    loop {
+        // Privileges are looked up by table name alone, like policies
+        tables.push(parse_table_name(parser)?.1);
+        if !parser.consume_token(&Token::Comma) {
+            break;
+        }
+    }
+
+    let (separator, name) = if grant { (Keyword::TO, "TO") } else { (Keyword::FROM, "FROM") };
+    if !parser.parse_keyword(separator) {
+        return expected(parser, &format!("{} after table name", name));
+    }
+    let roles = parse_roles(parser)?;
+
+    Ok(if grant {
+        RlsStatement::GrantPrivileges { privileges, tables, roles }
+    } else {
+        RlsStatement::RevokePrivileges { privileges, tables, roles }
+    })
+}
+
+/// Parse the optional `[WITH] { BYPASSRLS | NOBYPASSRLS }` of a role
+fn parse_bypass_option(parser: &mut Parser) -> Result<Option<bool>> {
+    let with = parser.parse_keyword(Keyword::WITH);
+    if parse_word(parser, "BYPASSRLS") {
+        Ok(Some(true))
+    } else if parse_word(parser, "NOBYPASSRLS") {
+        Ok(Some(false))
+    } else if with {
+        expected(parser, "BYPASSRLS or NOBYPASSRLS after WITH")
+    } else {
+        Ok(None)
+    }
+}
+
+/// Parse a list of role names, such as the one following `TO`
+///
+/// `PUBLIC` is recognized in any case and normalized to upper case.
+fn parse_roles(parser: &mut Parser) -> Result<Vec<String>> {
+    let roles = parse_with(parser, |p| p.parse_comma_separated(Parser::parse_identifier))?;
+    Ok(roles
+        .into_iter()
+        .map(|role| match role.quote_style {
+            None if role.value.eq_ignore_ascii_case(PUBLIC_ROLE) => PUBLIC_ROLE.to_string(),
+            _ => role.value,
+        })
+        .collect())
+}
+
+/// Parse the optional `USING (...)` and `WITH CHECK (...)` clauses of a policy
+fn parse_policy_expressions(parser: &mut Parser) -> Result<(Option<String>, Option<String>)> {
+    let using_expr = if parser.parse_keyword(Keyword::USING) {
+        Some(parse_parenthesized_expr(parser)?)
+    } else {
+        None
+    };
+
+    let check_expr = if parser.parse_keyword(Keyword::WITH) {
+        if !parser.parse_keyword(Keyword::CHECK) {
+            return expected(parser, "CHECK after WITH");
+        }
+        Some(parse_parenthesized_expr(parser)?)
+    } else {
+        None
+    };
+
+    Ok((using_expr, check_expr))
+}
+
+/// Parse everything following `ALTER TABLE` if it changes row level security
+///
+/// ```sql
+/// ALTER TABLE [ IF EXISTS ] [ ONLY ] table_name
+///     { ENABLE | DISABLE | FORCE | NO FORCE } ROW LEVEL SECURITY
+///
+/// ALTER TABLE [ IF EXISTS ] [ ONLY ] table_name OWNER TO role_name
+/// ```
+///
+/// Any other ALTER TABLE statement yields `None` and is left to libSQL.
+fn parse_alter_table(parser: &mut Parser) -> Result<Option<RlsStatement>> {
+    let _ = parser.parse_keywords(&[Keyword::IF, Keyword::EXISTS]);
+    let _ = parser.parse_keyword(Keyword::ONLY);
+    let location = parser.peek_token().location;
+    let Ok(name) = parser.parse_object_name() else {
+        return Ok(None);
+    };
+
+    if parse_word(parser, "OWNER") {
+        if !parser.parse_keyword(Keyword::TO) {
+            return expected(parser, "TO after OWNER");
+        }
+        let owner = parse_with(parser, Parser::parse_identifier)?.value;
+        expect_end(parser)?;
+        let (_, table_name) = split_table_name(name.0, location)?;
+        return Ok(Some(RlsStatement::AlterTableOwner { table_name, owner }));
+    }
+
+    let action = if parse_word(parser, "ENABLE") {
+        RowLevelSecurityAction::Enable
+    } else if parse_word(parser, "DISABLE") {
+        RowLevelSecurityAction::Disable
+    } else if parser.parse_keyword(Keyword::FORCE) {
+        RowLevelSecurityAction::Force
+    } else if parser.parse_keywords(&[Keyword::NO, Keyword::FORCE]) {
+        RowLevelSecurityAction::NoForce
+    } else {
+        return Ok(None);
+    };
+
+    if !(parser.parse_keywords(&[Keyword::ROW, Keyword::LEVEL]) && parse_word(parser, "SECURITY")) {
+        return expected(parser, "ROW LEVEL SECURITY");
+    }
+    expect_end(parser)?;
+
+    // Policies are looked up by table name alone, so the settings are too
+    let (_, table_name) = split_table_name(name.0, location)?;
+    Ok(Some(RlsStatement::AlterTableRowLevelSecurity {
+        table_name,
+        action,
+    }))
+}
+
+/// Parse an optionally schema-qualified table name
+fn parse_table_name(parser: &mut Parser) -> Result<(Option<String>, String)> {
+    let location = parser.peek_token().location;
+    let parts = parse_with(parser, Parser::parse_object_name)?.0;
+    split_table_name(parts, location)
+}
+
+/// Split the parts of a table name into its schema and table
+fn split_table_name(mut parts: Vec<Ident>, location: Location) -> Result<(Option<String>, String)> {
+    match parts.len() {
+        1 => Ok((None, parts.remove(0).value)),
+        2 => {
+            let table = parts.remove(1).value;
+            Ok((Some(parts.remove(0).value), table))
+        }
+        _ => Err(Error::Policy(format!(
+            "Expected table name of the form [schema.]table at Line: {}, Column {}",
+            location.line, location.column
+        ))),
+    }
+}
+
+/// Parse `( expr )` and return the normalized expression text
+fn parse_parenthesized_expr(parser: &mut Parser) -> Result<String> {
+    if !parser.consume_token(&Token::LParen) {
+        return expected(parser, "(");
+    }
+    let expr = parse_with(parser, Parser::parse_expr)?;
+    sql_parser::check_policy_placeholders(&expr)?;
+    if !parser.consume_token(&Token::RParen) {
+        return expected(parser, ")");
+    }
+    Ok(expr.to_string())
+}
+
+/// Consume a non-reserved word such as POLICY, case-insensitively
+fn parse_word(parser: &mut Parser, word: &str) -> bool {
+    match parser.peek_token().token {
+        Token::Word(w) if w.quote_style.is_none() && w.value.eq_ignore_ascii_case(word) => {
+            parser.next_token();
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Consume a non-reserved word, failing if it is missing
+fn expect_word(parser: &mut Parser, word: &str) -> Result<()> {
+    if !parse_word(parser, word) {
+        return expected(parser, word);
+    }
+    Ok(())
+}
+
+/// Ensure nothing but an optional semicolon follows the statement
+fn expect_end(parser: &mut Parser) -> Result<()> {
+    let _ = parser.consume_token(&Token::SemiColon);
+    if parser.peek_token().token != Token::EOF {
+        return expected(parser, "end of statement");
+    }
+    Ok(())
+}
+
+/// Run a sqlparser sub-parser, attaching the position it started at to any error
+fn parse_with<'a, T, F>(parser: &mut Parser<'a>, f: F) -> Result<T>
+where
+    F: FnOnce(&mut Parser<'a>) -> std::result::Result<T, ParserError>,
+{
+    let location = parser.peek_token().location;
+    f(parser).map_err(|e| {
+        let message = match e {
+            ParserError::ParserError(message) | ParserError::TokenizerError(message) => message,
+            ParserError::RecursionLimitExceeded => "Recursion limit exceeded".to_string(),
+        };
+        Error::Policy(format!(
+            "{} at Line: {}, Column {}",
+            message, location.line, location.column
+        ))
+    })
+}
+
+/// Build an error describing the token found instead of the expected one
+fn expected<T>(parser: &Parser, expected: &str) -> Result<T> {
+    let found = parser.peek_token();
+    Err(Error::Policy(format!(
+        "Expected {}, found: {} at Line: {}, Column {}",
+        expected, found, found.location.line, found.location.column
+    )))
+}
+
+/// Check whether the SQL text starts with the given words, ignoring case
+fn starts_with_words(sql: &str, words: &[&str]) -> bool {
+    let mut parts = sql.split_whitespace();
+    words
+        .iter()
+        .all(|word| parts.next().is_some_and(|part| part.eq_ignore_ascii_case(word)))
+}
@@ -0,0 +1,167 @@
+use crate::{Error, Result};
+use libsql::{Connection, params};
+
+/// The role every role is implicitly a member of
+pub const PUBLIC_ROLE: &str = "PUBLIC";
+
+/// Create a role in the `_rls_roles` catalog
+///
+/// Sessions with a `bypass` role skip row level security altogether.
+pub(crate) async fn create_role(conn: &Connection, name: &str, bypass: bool) -> Result<u64> {
+    if name.eq_ignore_ascii_case(PUBLIC_ROLE) {
+        return Err(Error::Policy(format!("Role name {} is reserved", name)));
+    }
+    // Policies store their roles as a comma-separated list
+    if name.contains(',') {
+        return Err(Error::Policy(format!("Role name {} must not contain a comma", name)));
+    }
+    if role_exists(conn, name).await? {
+        return Err(Error::Policy(format!("Role {} already exists", name)));
+    }
+    conn.execute("INSERT INTO _rls_roles (name, bypass) VALUES (?, ?)", params![name, bypass])
+        .await
+        .map_err(Into::into)
+}
+
+/// Change whether sessions with a role bypass row level security
+pub(crate) async fn set_bypass(conn: &Connection, name: &str, bypass: bool) -> Result<u64> {
+    let updated = conn.execute("UPDATE _rls_roles SET bypass = ? WHERE name = ?", params![bypass, name]).await?;
+    if updated == 0 {
+        return Err(role_not_found(name));
+    }
+    Ok(updated)
+}
+
+/// Check whether sessions with a role bypass row level security
+///
+/// As in Postgres, the attribute belongs to the role itself and is not
+/// inherited by its members.
+pub(crate) async fn has_bypass(conn: &Connection, name: &str) -> Result<bool> {
+    let mut rows = conn.query("SELECT bypass FROM _rls_roles WHERE name = ?", params![name]).await?;
+    match rows.next()? {
+        Some(row) => Ok(row.get::<i64>(0)? != 0),
+        None => Ok(false),
+    }
+}
+
+/// Remove a role and its memberships
+///
+/// Fails if the role does not exist, unless `if_exists` is set, or if a
+/// policy or masking policy still applies to it or it still holds
+/// privileges.
+pub(crate) async fn drop_role(conn: &Connection, name: &str, if_exists: bool) -> Result<u64> {
+    let mut rows = conn.query(
+        "SELECT 'policy', name, table_name, roles FROM _rls_policies
+         UNION ALL
+         SELECT 'masking policy', name, table_name, roles FROM _rls_masking_policies",
+        params![],
+    ).await?;
+    while let Some(row) = rows.next()? {
+        let roles: String = row.get(3)?;
+        if roles.split(',').any(|role| role == name) {
+            return Err(Error::Policy(format!(
+                "Role {} cannot be dropped because {} {} for table {} applies to it",
+                name,
+                row.get::<String>(0)?,
+                row.get::<String>(1)?,
+                row.get::<String>(2)?
+            )));
+        }
+    }
+
+    let mut rows = conn.query(
+        "SELECT table_name FROM _rls_grants WHERE role = ?1
+         UNION ALL
+         SELECT table_name FROM _rls_column_grants WHERE role = ?1
+         LIMIT 1",
+        params![name],
+    ).await?;
+    if let Some(row) = rows.next()? {
+        return Err(Error::Policy(format!(
+            "Role {} cannot be dropped because it holds privileges on table {}",
+            name,
+            row.get::<String>(0)?
+        )));
+    }
+
+    let removed = conn.execute("DELETE FROM _rls_roles WHERE name = ?", params![name]).await?;
+    if removed == 0 && !if_exists {
+        return Err(role_not_found(name));
+    }
+    conn.execute(
+        "DELETE FROM _rls_role_members WHERE role = ?1 OR member = ?1",
+        params![name],
+    ).await?;
+    Ok(removed)
+}
+
+/// Make `member` a member of `role`, so that the policies for `role` also
+/// apply to `member`
+pub(crate) async fn grant_role(conn: &Connection, role: &str, member: &str) -> Result<u64> {
+    ensure_roles_exist(conn, &[role, member]).await?;
+    if effective_roles(conn, Some(role)).await?.iter().any(|granted| granted == member) {
+        return Err(Error::Policy(format!(
+            "Granting role {} to {} would make a role a member of itself",
+            role, member
+        )));
+    }
+    conn.execute(
+        "INSERT INTO _rls_role_members (role, member) VALUES (?, ?) ON CONFLICT DO NOTHING",
+        params![role, member],
+    ).await.map_err(Into::into)
+}
+
+/// Remove `member` from `role`
+pub(crate) async fn revoke_role(conn: &Connection, role: &str, member: &str) -> Result<u64> {
+    ensure_roles_exist(conn, &[role, member]).await?;
+    conn.execute(
+        "DELETE FROM _rls_role_members WHERE role = ? AND member = ?",
+        params![role, member],
+    ).await.map_err(Into::into)
+}
+
+/// Get the roles whose policies apply to a session with the given role
+///
+/// These are the role itself, every role it is a member of, directly or
+/// through other roles, and `PUBLIC`. A session without a role only gets
+/// `PUBLIC`.
+pub(crate) async fn effective_roles(conn: &Connection, role: Option<&str>) -> Result<Vec<String>> {
+    let mut roles = Vec::new();
+    if let Some(role) = role {
+        let mut rows = conn.query(
+            "WITH RECURSIVE effective(role) AS (
+                SELECT ?
+                UNION
+                SELECT m.role FROM _rls_role_members AS m JOIN effective AS e ON m.member = e.role
+            )
+            SELECT role FROM effective",
+            params![role],
+        ).await?;
+        while let Some(row) = rows.next()? {
+            roles.push(row.get(0)?);
+        }
+    }
+    roles.push(PUBLIC_ROLE.to_string());
+    Ok(roles)
+}
+
+/// Fail unless every role exists, treating `PUBLIC` as always existing
+pub(crate) async fn ensure_roles_exist(conn: &Connection, roles: &[impl AsRef<str>]) -> Result<()> {
+    for role in roles {
+        let role = role.as_ref();
+        if role != PUBLIC_ROLE && !role_exists(conn, role).await? {
+            return Err(role_not_found(role));
+        }
+    }
+    Ok(())
+}
+
+async fn role_exists(conn: &Connection, name: &str) -> Result<bool> {
+    let mut rows = conn.query("SELECT 1 FROM _rls_roles WHERE name = ?", params![name]).await?;
+    Ok(rows.next()?.is_some())
+}
+
+/// Build the error reported when a role does not exist
+fn role_not_found(name: &str) -> Error {
+    Error::Policy(format!("Role {} does not exist", name))
+}
@@ -0,0 +1,36 @@
+use libsql::Value;
+use std::collections::HashMap;
+
+/// Context key read by the `current_user_id()` SQL function
+pub const USER_ID_KEY: &str = "rls.user_id";
+
+/// Context key read by the `current_role()` SQL function
+pub const ROLE_KEY: &str = "rls.role";
+
+/// Session state that policies can read through SQL functions
+///
+/// Values are set per connection, typically at the start of each request,
+/// and are exposed to SQL as `current_user_id()`, `current_role()` and
+/// `current_setting('key')`. Missing keys evaluate to NULL so that policies
+/// comparing against them fail closed.
+#[derive(Debug, Clone, Default)]
+pub struct SessionContext {
+    values: HashMap<String, Value>,
+}
+
+impl SessionContext {
+    /// Get the value stored under a key
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.values.get(key)
+    }
+
+    /// Store a value under a key, replacing any previous value
+    pub fn set(&mut self, key: impl Into<String>, value: impl Into<Value>) {
+        self.values.insert(key.into(), value.into());
+    }
+
+    /// Remove all values from the context
+    pub fn clear(&mut self) {
+        self.values.clear();
+    }
+}
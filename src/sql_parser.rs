@@ -1,14 +1,35 @@
-use crate::{policy::Policy, Error, Result};
+use crate::{
+    policy::{Policy, PolicyKind, CATALOG_PREFIX},
+    privilege::Privilege,
+    session::{SessionContext, ROLE_KEY, USER_ID_KEY},
+    Error, Result,
+};
 use sqlparser::ast::{
-    Expr, Select, SetExpr, Statement, TableFactor, Value,
+    visit_expressions, visit_expressions_mut, visit_relations, AlterTableOperation, BinaryOperator, Expr, FunctionArg,
+    FunctionArgExpr, Ident, JoinConstraint, JoinOperator, ObjectName, ObjectType, OnConflict, OnConflictAction,
+    OnInsert, Query, Select, SelectItem, SetExpr, SqliteOnConflict, Statement, TableAlias, TableFactor, TableWithJoins,
+    Value, Visit, VisitMut, VisitorMut, WildcardAdditionalOptions,
 };
-use sqlparser::dialect::SQLiteDialect;
+use sqlparser::dialect::{GenericDialect, SQLiteDialect};
+use sqlparser::keywords::Keyword;
 use sqlparser::parser::Parser;
+use sqlparser::tokenizer::{Token, Tokenizer};
+use std::collections::HashMap;
+use std::ops::ControlFlow;
 
 /// Parse an SQL statement into a Statement AST
 pub fn parse_sql(sql: &str) -> Result<Statement> {
-    let dialect = SQLiteDialect {};
-    let mut statements = Parser::parse_sql(&dialect, sql)?;
+    // The SQLite dialect rejects parts of SQLite's grammar, such as
+    // UPDATE ... FROM, which the generic dialect accepts
+    let mut statements = match Parser::parse_sql(&SQLiteDialect {}, sql) {
+        Ok(statements) => statements,
+        Err(e) => Parser::parse_sql(&GenericDialect {}, sql)
+            .or_else(|_| match filter_unfiltered_delete(sql) {
+                Some(sql) => Parser::parse_sql(&SQLiteDialect {}, &sql),
+                None => Err(e.clone()),
+            })
+            .map_err(|_| e)?,
+    };
 
     if statements.len() != 1 {
         return Err(Error::UnsupportedSql(
@@ -19,93 +40,1601 @@ pub fn parse_sql(sql: &str) -> Result<Statement> {
     Ok(statements.pop().unwrap())
 }
 
-/// Extract the names of all tables referenced in a SELECT statement
+/// Add `WHERE true` to a `DELETE FROM t RETURNING ...` statement
+///
+/// sqlparser reads `RETURNING` directly after the table name as the table's
+/// alias, so the statement only parses once it has a WHERE clause. Returns
+/// `None` for any other statement.
+fn filter_unfiltered_delete(sql: &str) -> Option<String> {
+    let tokens = Tokenizer::new(&SQLiteDialect {}, sql).tokenize().ok()?;
+    let mut words = tokens
+        .iter()
+        .enumerate()
+        .filter(|(_, token)| !matches!(token, Token::Whitespace(_)));
+
+    let mut is_keyword = |keyword: Keyword| {
+        matches!(words.next(), Some((_, Token::Word(word))) if word.keyword == keyword)
+    };
+    if !is_keyword(Keyword::DELETE) || !is_keyword(Keyword::FROM) {
+        return None;
+    }
+    // The table name, possibly qualified with its schema
+    let returning = loop {
+        match words.next()? {
+            (index, Token::Word(word)) if word.keyword == Keyword::RETURNING && word.quote_style.is_none() => {
+                break index
+            }
+            (_, Token::Word(_)) | (_, Token::Period) => {}
+            _ => return None,
+        }
+    };
+
+    let mut sql: String = tokens[..returning].iter().map(ToString::to_string).collect();
+    sql.push_str(" WHERE true ");
+    sql.extend(tokens[returning..].iter().map(ToString::to_string));
+    Some(sql)
+}
+
+/// Check whether a statement refers to the RLS catalog tables
+///
+/// Looks for any identifier starting with `_rls_` in the statement as
+/// compiled back to SQL, which is the SQL that runs, so that comments SQLite
+/// reads differently from the parser can't hide one. Single-quoted strings
+/// are included too, as SQLite accepts them as table names.
+pub fn references_catalog(statement: &Statement) -> bool {
+    let sql = compile_ast_to_sql(statement);
+    match Tokenizer::new(&SQLiteDialect {}, &sql).tokenize() {
+        Ok(tokens) => tokens.iter().any(|token| match token {
+            Token::Word(word) => is_catalog_name(&word.value),
+            Token::SingleQuotedString(string) => is_catalog_name(string),
+            _ => false,
+        }),
+        Err(_) => mentions_catalog(&sql),
+    }
+}
+
+/// Check whether SQL that can't be parsed might refer to the RLS catalog
+/// tables
+///
+/// Without a parse, comments can't be told apart from the rest of the SQL,
+/// so any mention of `_rls_` counts.
+pub fn mentions_catalog(sql: &str) -> bool {
+    sql.to_ascii_lowercase().contains(CATALOG_PREFIX)
+}
+
+fn is_catalog_name(name: &str) -> bool {
+    name.get(..CATALOG_PREFIX.len())
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case(CATALOG_PREFIX))
+}
+
+/// Check whether a statement other than a query, INSERT, UPDATE or DELETE
+/// can run without row level security applying to it
+///
+/// These are transaction control and DDL that runs no queries:
+/// `CREATE TABLE` without `AS SELECT`, `CREATE INDEX`, `ALTER TABLE ... ADD
+/// COLUMN` and `DROP`. Views and triggers are not allowed, as the statements
+/// they run would not be rewritten, and neither is renaming a table or
+/// renaming or dropping a column, which would leave its policies behind.
+/// Temporary tables and DDL on protected tables are further limited to
+/// admin connections, see `creates_temporary_table` and
+/// `dropped_or_indexed_tables`.
+pub fn is_known_safe_statement(statement: &Statement) -> bool {
+    match statement {
+        Statement::StartTransaction { .. }
+        | Statement::Commit { .. }
+        | Statement::Rollback { .. }
+        | Statement::Savepoint { .. }
+        | Statement::CreateIndex { .. } => true,
+        Statement::AlterTable { operation, .. } => matches!(operation, AlterTableOperation::AddColumn { .. }),
+        Statement::CreateTable { query, .. } => query.is_none(),
+        Statement::Drop { object_type, .. } => {
+            matches!(object_type, ObjectType::Table | ObjectType::Index | ObjectType::View)
+        }
+        _ => false,
+    }
+}
+
+/// Check whether a statement creates a temporary table
+///
+/// A temporary table hides the table of the same name in the main schema,
+/// including from the unqualified names policies read.
+pub fn creates_temporary_table(statement: &Statement) -> bool {
+    let in_temp_schema = |name: &ObjectName| {
+        name.0.len() > 1
+            && ["temp", "temporary"].iter().any(|schema| name.0[0].value.eq_ignore_ascii_case(schema))
+    };
+    match statement {
+        Statement::CreateTable { temporary, name, .. } => *temporary || in_temp_schema(name),
+        Statement::CreateView { name, .. } | Statement::CreateVirtualTable { name, .. } => in_temp_schema(name),
+        _ => false,
+    }
+}
+
+/// Get the tables whose rows a statement removes or constrains without
+/// reading or writing them: those named by `DROP TABLE` or `DROP VIEW`, and
+/// the table of `CREATE INDEX`
+pub fn dropped_or_indexed_tables(statement: &Statement) -> Vec<String> {
+    let names = match statement {
+        Statement::Drop { object_type: ObjectType::Table | ObjectType::View, names, .. } => names.iter().collect(),
+        Statement::CreateIndex { table_name, .. } => vec![table_name],
+        _ => Vec::new(),
+    };
+    names.into_iter().filter_map(|name| name.0.last()).map(|ident| ident.value.clone()).collect()
+}
+
+/// Extract the names of all tables referenced anywhere in a statement,
+/// including joins, derived tables and subqueries
 pub fn extract_table_references(statement: &Statement) -> Vec<String> {
     let mut tables = Vec::new();
 
-    if let Statement::Query(query) = statement {
-        if let SetExpr::Select(select) = &*query.body {
-            for table_with_joins in &select.from {
-                if let TableFactor::Table { name, .. } = &table_with_joins.relation {
-                    tables.push(name.to_string());
+    let _ = visit_relations(statement, |name| {
+        // Use the unquoted name so it matches the policy catalog
+        if let Some(ident) = name.0.last() {
+            if !tables.contains(&ident.value) {
+                tables.push(ident.value.clone());
+            }
+        }
+        ControlFlow::<()>::Continue(())
+    });
+
+    tables
+}
+
+/// Get the privileges a statement needs on the tables it references
+///
+/// The target of an INSERT, UPDATE or DELETE needs the matching privilege,
+/// along with UPDATE for an upsert and DELETE for `REPLACE`, and SELECT
+/// when the statement reads its rows in a WHERE, SET or RETURNING clause.
+/// Every other table a query or data-modifying statement references needs
+/// SELECT. Other statements need no table privileges, though only the
+/// owner of a table under privilege checks can drop or index it.
+pub fn required_privileges(statement: &Statement) -> Vec<(String, Privilege)> {
+    let mut required = Vec::new();
+    match statement {
+        Statement::Query(_) => {}
+        Statement::Insert { or, on, returning, .. } => {
+            required.push(Privilege::Insert);
+            if let Some(SqliteOnConflict::Replace) = or {
+                required.push(Privilege::Delete);
+            }
+            if let Some(OnInsert::OnConflict(OnConflict {
+                action: OnConflictAction::DoUpdate(update),
+                ..
+            })) = on
+            {
+                required.push(Privilege::Update);
+                if update.selection.is_some() || reads_columns(&update.assignments) {
+                    required.push(Privilege::Select);
                 }
             }
+            if returning.is_some() {
+                required.push(Privilege::Select);
+            }
         }
+        Statement::Update { assignments, selection, returning, .. } => {
+            required.push(Privilege::Update);
+            if selection.is_some() || returning.is_some() || reads_columns(assignments) {
+                required.push(Privilege::Select);
+            }
+        }
+        Statement::Delete { selection, returning, .. } => {
+            required.push(Privilege::Delete);
+            if selection.is_some() || returning.is_some() {
+                required.push(Privilege::Select);
+            }
+        }
+        _ => return Vec::new(),
     }
 
-    tables
+    let mut references = Vec::new();
+    let _ = visit_relations(statement, |name| {
+        if let Some(ident) = name.0.last() {
+            references.push(ident.value.clone());
+        }
+        ControlFlow::<()>::Continue(())
+    });
+
+    let mut privileges = Vec::new();
+    if let Some(target) = target_table(statement) {
+        // The target itself is written; any other reference to it is a read
+        if let Some(position) = references.iter().position(|table| table.eq_ignore_ascii_case(&target.value)) {
+            references.remove(position);
+        }
+        privileges.extend(required.into_iter().map(|privilege| (target.value.clone(), privilege)));
+    }
+    privileges.extend(references.into_iter().map(|table| (table, Privilege::Select)));
+
+    let mut unique = Vec::new();
+    for privilege in privileges {
+        if !unique.contains(&privilege) {
+            unique.push(privilege);
+        }
+    }
+    unique
+}
+
+/// Check whether the expressions of a node read any column, other than
+/// the proposed row of an upsert, `excluded`
+fn reads_columns<V: Visit>(node: &V) -> bool {
+    let found = visit_expressions(node, |expr| match expr {
+        Expr::Identifier(_) => ControlFlow::Break(()),
+        Expr::CompoundIdentifier(idents) if !idents[0].value.eq_ignore_ascii_case("excluded") => ControlFlow::Break(()),
+        _ => ControlFlow::Continue(()),
+    });
+    found.is_break()
+}
+
+/// Check whether the expressions of a node read a rowid, under any of the
+/// names SQLite gives it
+fn reads_rowid<V: Visit>(node: &V) -> bool {
+    let found = visit_expressions(node, |expr| match expr {
+        Expr::Identifier(ident) if is_rowid_name(&ident.value) => ControlFlow::Break(()),
+        Expr::CompoundIdentifier(idents) if idents.last().is_some_and(|ident| is_rowid_name(&ident.value)) => {
+            ControlFlow::Break(())
+        }
+        _ => ControlFlow::Continue(()),
+    });
+    found.is_break()
+}
+
+/// Check whether a column name refers to the rowid of a table without a
+/// column of that name
+fn is_rowid_name(name: &str) -> bool {
+    ["rowid", "oid", "_rowid_"].iter().any(|rowid| name.eq_ignore_ascii_case(rowid))
+}
+
+/// How policies are added to a query
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RewriteStrategy {
+    /// AND each protected table's policy onto the WHERE clause of the query,
+    /// with the policy's columns qualified by the table's alias
+    ///
+    /// This produces the simplest SQL, but turns outer joins of protected
+    /// tables into inner joins.
+    WhereClause,
+    /// Replace each protected table with `(SELECT * FROM t WHERE policy) AS t`
+    ///
+    /// The policy filters the table before it is joined or filtered by the
+    /// query, so LEFT, RIGHT and FULL joins keep their meaning. The derived
+    /// table has no rowid, so statements naming `rowid`, `oid` or `_rowid_`
+    /// fall back to `WhereClause`.
+    #[default]
+    DerivedTable,
+}
+
+/// A column of a table with masking policies, along with the expression
+/// read in place of its values if it is masked
+#[derive(Debug, Clone)]
+pub struct MaskedColumn {
+    pub name: String,
+    pub mask: Option<Expr>,
 }
 
-/// Apply RLS policies to a SELECT statement
-pub fn apply_rls_to_select(statement: &mut Statement, policies: &[Policy]) -> Result<()> {
-    if let Statement::Query(query) = statement {
-        if let SetExpr::Select(select) = &mut *query.body {
-            for policy in policies {
-                if let Some(using_expr) = &policy.using_expr {
-                    apply_policy_to_select(select, using_expr)?;
+/// Apply SELECT policies and column masks to the tables a statement reads
+///
+/// `policies` maps each protected table to its policies, which are combined
+/// with `combine_policy_expressions`. Every reference to a protected table is
+/// filtered according to `strategy`, whether it is joined, nested in a
+/// derived table or read by a subquery in any expression, including the
+/// source of an INSERT and the RETURNING clause of data-modifying statements. Their target table is left
+/// to `restrict_target_rows` and the policy checks.
+///
+/// `masks` maps each table with masking policies to its columns. References
+/// to such a table are replaced with `(SELECT id, <mask> AS email, ... FROM
+/// t WHERE policy) AS t` whatever the strategy, so that every expression
+/// reading a masked column, in any clause or subquery, reads the mask while
+/// the policies still see the actual values. In the target of a
+/// data-modifying statement, the masks replace the references to its
+/// masked columns instead. Returns whether the statement was changed.
+pub fn apply_rls_to_statement(
+    statement: &mut Statement,
+    policies: &HashMap<String, Vec<Policy>>,
+    masks: &HashMap<String, Vec<MaskedColumn>>,
+    strategy: RewriteStrategy,
+) -> Result<bool> {
+    let strategy = match strategy {
+        RewriteStrategy::DerivedTable if reads_rowid(statement) => RewriteStrategy::WhereClause,
+        strategy => strategy,
+    };
+    let mut rewriter = QueryRewriter {
+        policies,
+        masks,
+        strategy,
+        ctes: Vec::new(),
+        modified: false,
+    };
+    rewriter.modified |= mask_target_columns(statement, masks)?;
+
+    match statement {
+        Statement::Query(query) => rewriter.rewrite_query(query)?,
+        Statement::Update {
+            assignments,
+            from,
+            selection,
+            returning,
+            ..
+        } => {
+            let mut conditions = Vec::new();
+            if let Some(from) = from {
+                rewriter.rewrite_tables_with_joins(std::slice::from_mut(from), &mut conditions)?;
+            }
+            rewriter.rewrite_subqueries(assignments)?;
+            rewriter.rewrite_subqueries(selection)?;
+            rewriter.rewrite_subqueries(returning)?;
+            for condition in conditions {
+                add_condition(selection, condition);
+            }
+        }
+        Statement::Insert { source, returning, .. } => {
+            rewriter.rewrite_query(source)?;
+            rewriter.rewrite_subqueries(returning)?;
+        }
+        Statement::Delete {
+            using,
+            selection,
+            returning,
+            ..
+        } => {
+            let mut conditions = Vec::new();
+            if let Some(using) = using {
+                rewriter.rewrite_tables_with_joins(using, &mut conditions)?;
+            }
+            rewriter.rewrite_subqueries(selection)?;
+            rewriter.rewrite_subqueries(returning)?;
+            for condition in conditions {
+                add_condition(selection, condition);
+            }
+        }
+        _ => {}
+    }
+
+    Ok(rewriter.modified)
+}
+
+/// Replace the references to the masked columns of a data-modifying
+/// statement's target with their masks
+///
+/// The target can't be replaced with a masked derived table, so the masks
+/// take the place of its columns in the statement's WHERE, RETURNING and
+/// `ON CONFLICT ... WHERE` clauses instead. Returns whether the statement
+/// changed.
+///
+/// Assignments reading a masked column fail with `Error::PermissionDenied`
+/// instead, as they would write the masked value over the actual one.
+fn mask_target_columns(statement: &mut Statement, masks: &HashMap<String, Vec<MaskedColumn>>) -> Result<bool> {
+    let (name, alias) = match &*statement {
+        Statement::Insert { table_name, .. } => (table_name.clone(), None),
+        Statement::Update { table, .. } => match &table.relation {
+            TableFactor::Table { name, alias, .. } => (name.clone(), alias.clone()),
+            _ => return Ok(false),
+        },
+        Statement::Delete { from, .. } => match from.first().map(|table| &table.relation) {
+            Some(TableFactor::Table { name, alias, .. }) => (name.clone(), alias.clone()),
+            _ => return Ok(false),
+        },
+        _ => return Ok(false),
+    };
+    let Some((table_name, columns)) = name.0.last().and_then(|ident| masks.get_key_value(&ident.value)) else {
+        return Ok(false);
+    };
+
+    let qualifier = table_qualifier(&name, &alias);
+    let mut masker = ColumnMasker {
+        table_name,
+        qualifier: &qualifier,
+        masks: columns
+            .iter()
+            .filter_map(|column| {
+                let mut mask = column.mask.clone()?;
+                qualify_columns(&mut mask, table_name, &qualifier);
+                Some((column.name.as_str(), mask))
+            })
+            .collect(),
+        subquery_depth: 0,
+        modified: false,
+    };
+    let assignments = match statement {
+        Statement::Insert { on, returning, .. } => {
+            let _ = returning.visit(&mut masker);
+            match on {
+                Some(OnInsert::OnConflict(OnConflict {
+                    action: OnConflictAction::DoUpdate(update),
+                    ..
+                })) => {
+                    let _ = VisitMut::visit(&mut update.selection, &mut masker);
+                    Some(&mut update.assignments)
                 }
+                _ => None,
             }
         }
+        Statement::Update { assignments, selection, returning, .. } => {
+            let _ = selection.visit(&mut masker);
+            let _ = returning.visit(&mut masker);
+            Some(assignments)
+        }
+        Statement::Delete { selection, returning, .. } => {
+            let _ = selection.visit(&mut masker);
+            let _ = returning.visit(&mut masker);
+            None
+        }
+        _ => None,
+    };
+    if let Some(assignments) = assignments {
+        let modified = std::mem::take(&mut masker.modified);
+        let _ = assignments.visit(&mut masker);
+        if masker.modified {
+            return Err(Error::PermissionDenied(format!(
+                "Assignments can't read the masked columns of table {}",
+                table_name
+            )));
+        }
+        masker.modified = modified;
     }
+    Ok(masker.modified)
+}
 
-    Ok(())
+/// Replaces the masked columns of a table with their masks
+///
+/// Bare columns are only replaced outside subqueries, where they can't
+/// refer to the subquery's own tables, while columns qualified with the
+/// table are replaced anywhere.
+struct ColumnMasker<'a> {
+    table_name: &'a str,
+    qualifier: &'a [Ident],
+    /// The masked columns and their masks, qualified like the table
+    masks: Vec<(&'a str, Expr)>,
+    subquery_depth: usize,
+    modified: bool,
+}
+
+impl VisitorMut for ColumnMasker<'_> {
+    type Break = ();
+
+    fn pre_visit_expr(&mut self, expr: &mut Expr) -> ControlFlow<Self::Break> {
+        if is_subquery(expr) {
+            self.subquery_depth += 1;
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn post_visit_expr(&mut self, expr: &mut Expr) -> ControlFlow<Self::Break> {
+        if is_subquery(expr) {
+            self.subquery_depth -= 1;
+            return ControlFlow::Continue(());
+        }
+        let column = match expr {
+            Expr::Identifier(column) if self.subquery_depth == 0 => column,
+            Expr::CompoundIdentifier(idents)
+                if idents.len() == 2
+                    && (idents[0].value.eq_ignore_ascii_case(self.table_name)
+                        || self.qualifier.last().is_some_and(|q| q.value.eq_ignore_ascii_case(&idents[0].value))) =>
+            {
+                &idents[1]
+            }
+            _ => return ControlFlow::Continue(()),
+        };
+        if let Some((_, mask)) = self.masks.iter().find(|(name, _)| name.eq_ignore_ascii_case(&column.value)) {
+            *expr = Expr::Nested(Box::new(mask.clone()));
+            self.modified = true;
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// Restrict the rows a data-modifying statement targets to those matching
+/// a policy condition
+///
+/// The condition's columns are qualified with the target table's alias so
+/// they cannot be confused with columns of other tables in the statement.
+pub fn restrict_target_rows(statement: &mut Statement, mut condition: Expr) {
+    let (table, selection) = match statement {
+        Statement::Update { table, selection, .. } => (&table.relation, selection),
+        Statement::Delete { from, selection, .. } => match from.first() {
+            Some(table) => (&table.relation, selection),
+            None => return,
+        },
+        _ => return,
+    };
+
+    if let TableFactor::Table { name, alias, .. } = table {
+        if let Some(table_name) = name.0.last() {
+            qualify_columns(&mut condition, &table_name.value, &table_qualifier(name, alias));
+        }
+    }
+    add_condition(selection, condition);
+}
+
+/// Get the name of the table a data-modifying statement writes to
+pub fn target_table(statement: &Statement) -> Option<&Ident> {
+    let relation = match statement {
+        Statement::Insert { table_name, .. } => return table_name.0.last(),
+        Statement::Update { table, .. } => &table.relation,
+        Statement::Delete { from, .. } => &from.first()?.relation,
+        _ => return None,
+    };
+    match relation {
+        TableFactor::Table { name, .. } => name.0.last(),
+        _ => None,
+    }
+}
+
+/// Get the qualifier of a table's columns: its alias, or else its name
+pub fn table_qualifier(name: &ObjectName, alias: &Option<TableAlias>) -> Vec<Ident> {
+    match alias {
+        Some(alias) => vec![alias.name.clone()],
+        None => name.0.clone(),
+    }
+}
+
+/// Walks a query, filtering every protected table it reads
+///
+/// Relations are walked by hand, while the expressions of each query are
+/// searched for subqueries with a `SubqueryRewriter`. Tables replaced by
+/// filtered derived tables are never walked again, so each reference is
+/// filtered exactly once.
+struct QueryRewriter<'a> {
+    policies: &'a HashMap<String, Vec<Policy>>,
+    masks: &'a HashMap<String, Vec<MaskedColumn>>,
+    strategy: RewriteStrategy,
+    /// Names of the common table expressions in scope, which shadow tables
+    ctes: Vec<String>,
+    modified: bool,
 }
 
-/// Apply a policy expression to a SELECT statement
-fn apply_policy_to_select(select: &mut Box<Select>, policy_expr: &str) -> Result<()> {
-    // Parse the policy expression
-    let policy_condition = parse_policy_expression(policy_expr)?;
+impl QueryRewriter<'_> {
+    fn rewrite_query(&mut self, query: &mut Query) -> Result<()> {
+        let scope = self.ctes.len();
+        let result = self.rewrite_query_in_scope(query);
+        self.ctes.truncate(scope);
+        result
+    }
+
+    fn rewrite_query_in_scope(&mut self, query: &mut Query) -> Result<()> {
+        if let Some(with) = &mut query.with {
+            for cte in &mut with.cte_tables {
+                // SQLite lets any CTE refer to itself, with or without
+                // RECURSIVE, so its name is in scope within its own body
+                self.ctes.push(cte.alias.name.value.clone());
+                self.rewrite_query(&mut cte.query)?;
+            }
+        }
+
+        self.rewrite_set_expr(&mut query.body)?;
+
+        // ORDER BY, LIMIT and OFFSET may hold subqueries of their own
+        self.rewrite_subqueries(&mut query.order_by)?;
+        self.rewrite_subqueries(&mut query.limit)?;
+        self.rewrite_subqueries(&mut query.offset)
+    }
+
+    /// Check whether a relation name refers to a common table expression
+    fn is_cte(&self, name: &ObjectName) -> bool {
+        match name.0.as_slice() {
+            [ident] => self.ctes.iter().any(|cte| cte.eq_ignore_ascii_case(&ident.value)),
+            _ => false,
+        }
+    }
+
+    fn rewrite_set_expr(&mut self, body: &mut SetExpr) -> Result<()> {
+        match body {
+            SetExpr::Select(select) => self.rewrite_select(select),
+            SetExpr::Query(query) => self.rewrite_query(query),
+            // Each branch of UNION, INTERSECT and EXCEPT reads its own tables
+            SetExpr::SetOperation { left, right, .. } => {
+                self.rewrite_set_expr(left)?;
+                self.rewrite_set_expr(right)
+            }
+            SetExpr::Values(values) => self.rewrite_subqueries(values),
+            // `TABLE t` and data-modifying CTEs are not part of SQLite's grammar
+            SetExpr::Insert(_) | SetExpr::Update(_) | SetExpr::Table(_) => Ok(()),
+        }
+    }
+
+    fn rewrite_select(&mut self, select: &mut Select) -> Result<()> {
+        // The FROM clause is walked separately so that derived tables are
+        // rewritten as queries rather than searched for subqueries
+        let mut from = std::mem::take(&mut select.from);
+        let mut conditions = Vec::new();
+        let result = self
+            .rewrite_subqueries(select)
+            .and_then(|_| self.rewrite_tables_with_joins(&mut from, &mut conditions));
+        select.from = from;
+        result?;
+
+        for condition in conditions {
+            add_condition(&mut select.selection, condition);
+        }
+
+        Ok(())
+    }
+
+    fn rewrite_tables_with_joins(&mut self, from: &mut [TableWithJoins], conditions: &mut Vec<Expr>) -> Result<()> {
+        for table_with_joins in from {
+            self.rewrite_table_factor(&mut table_with_joins.relation, conditions)?;
+            for join in &mut table_with_joins.joins {
+                self.rewrite_table_factor(&mut join.relation, conditions)?;
+                self.rewrite_subqueries(&mut join.join_operator)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn rewrite_table_factor(&mut self, relation: &mut TableFactor, conditions: &mut Vec<Expr>) -> Result<()> {
+        match relation {
+            TableFactor::Table { name, .. } if self.is_cte(name) => Ok(()),
+            TableFactor::Table { name, alias, args: None, .. } => {
+                let Some(ident) = name.0.last() else {
+                    return Ok(());
+                };
+                let policies = self.policies.get_key_value(&ident.value);
+                let masks = self.masks.get(&ident.value);
+                if policies.is_none() && masks.is_none() {
+                    return Ok(());
+                }
+
+                let condition = policies
+                    .map(|(_, table_policies)| combine_policy_expressions(table_policies))
+                    .transpose()?;
+                match (self.strategy, condition, policies) {
+                    // Masked tables are always replaced, as the masks must
+                    // apply to every clause of the query
+                    (RewriteStrategy::WhereClause, Some(mut condition), Some((table_name, _))) if masks.is_none() => {
+                        qualify_columns(&mut condition, table_name, &table_qualifier(name, alias));
+                        conditions.push(condition);
+                    }
+                    (_, condition, _) => {
+                        let derived = filtered_table(name.clone(), alias.take(), condition, masks.map(Vec::as_slice));
+                        *relation = derived;
+                    }
+                }
+                self.modified = true;
+                Ok(())
+            }
+            TableFactor::Derived { subquery, .. } => self.rewrite_query(subquery),
+            TableFactor::NestedJoin { table_with_joins, .. } => {
+                self.rewrite_tables_with_joins(std::slice::from_mut(table_with_joins), conditions)
+            }
+            TableFactor::TableFunction { expr, .. } => self.rewrite_subqueries(expr),
+            _ => Ok(()),
+        }
+    }
+
+    /// Rewrite the outermost subqueries found in the expressions of a node
+    fn rewrite_subqueries<V: VisitMut>(&mut self, node: &mut V) -> Result<()> {
+        let mut visitor = SubqueryRewriter {
+            rewriter: self,
+            depth: 0,
+        };
+        match node.visit(&mut visitor) {
+            ControlFlow::Break(e) => Err(e),
+            ControlFlow::Continue(()) => Ok(()),
+        }
+    }
+}
+
+/// Rewrites each outermost subquery of an expression as a query
+///
+/// Subqueries nested within another are left to the rewrite of the outer
+/// one, which is why the visitor tracks its subquery depth.
+struct SubqueryRewriter<'r, 'a> {
+    rewriter: &'r mut QueryRewriter<'a>,
+    depth: usize,
+}
+
+impl VisitorMut for SubqueryRewriter<'_, '_> {
+    type Break = Error;
+
+    fn pre_visit_expr(&mut self, expr: &mut Expr) -> ControlFlow<Self::Break> {
+        if is_subquery(expr) {
+            self.depth += 1;
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn post_visit_expr(&mut self, expr: &mut Expr) -> ControlFlow<Self::Break> {
+        if !is_subquery(expr) {
+            return ControlFlow::Continue(());
+        }
+        self.depth -= 1;
+        if self.depth > 0 {
+            return ControlFlow::Continue(());
+        }
+
+        let query = match expr {
+            Expr::Subquery(query) | Expr::ArraySubquery(query) => query,
+            Expr::Exists { subquery, .. } | Expr::InSubquery { subquery, .. } => subquery,
+            _ => unreachable!(),
+        };
+        match self.rewriter.rewrite_query(query) {
+            Ok(()) => ControlFlow::Continue(()),
+            Err(e) => ControlFlow::Break(e),
+        }
+    }
+}
+
+/// The columns of a table a session may read, when its column privileges
+/// don't cover them all
+#[derive(Debug, Clone, Default)]
+pub struct ReadableColumns {
+    /// The readable columns, in declaration order
+    pub permitted: Vec<String>,
+    pub forbidden: Vec<String>,
+    /// The table's `INTEGER PRIMARY KEY` column, which `rowid`, `oid` and
+    /// `_rowid_` also name
+    pub rowid_alias: Option<String>,
+}
+
+impl ReadableColumns {
+    /// Check whether a name refers to a column the session may not read,
+    /// directly or as a rowid aliased by a forbidden column
+    fn is_forbidden(&self, name: &str) -> bool {
+        let names = |column: &String| column.eq_ignore_ascii_case(name);
+        if self.forbidden.iter().any(names) {
+            return true;
+        }
+        is_rowid_name(name)
+            && !self.permitted.iter().any(names)
+            && self.rowid_alias.as_ref().is_some_and(|alias| self.forbidden.contains(alias))
+    }
+}
+
+/// Limit a statement to the columns the session may read
+///
+/// `*` and `t.*` over a table in `restricted` are expanded into its
+/// permitted columns, while any other reference to one of its forbidden
+/// columns, in the projection, WHERE, ORDER BY or any other clause and in
+/// subqueries, fails with `Error::PermissionDenied`. An unqualified column
+/// is rejected whenever it could name a forbidden column of a table in
+/// scope. Returns whether the statement was changed.
+pub fn restrict_columns(statement: &mut Statement, restricted: &HashMap<String, ReadableColumns>) -> Result<bool> {
+    let mut restrictor = ColumnRestrictor {
+        restricted,
+        ctes: Vec::new(),
+        scopes: Vec::new(),
+        modified: false,
+    };
+
+    match statement {
+        Statement::Query(query) => restrictor.restrict_query(query)?,
+        Statement::Insert { table_name, source, on, returning, .. } => {
+            restrictor.restrict_query(source)?;
+            let target = restrictor.scoped_table(table_name, &None);
+            restrictor.scopes.push(vec![target]);
+            restrictor.check_columns(on)?;
+            if let Some(returning) = returning {
+                restrictor.restrict_projection(returning)?;
+            }
+        }
+        Statement::Update { table, assignments, from, selection, returning } => {
+            let mut tables = restrictor.scope_tables(std::slice::from_mut(table))?;
+            if let Some(from) = from {
+                tables.extend(restrictor.scope_tables(std::slice::from_mut(from))?);
+            }
+            restrictor.scopes.push(tables);
+            restrictor.check_join_constraints(std::slice::from_mut(table))?;
+            restrictor.check_join_constraints(from.as_mut_slice())?;
+            restrictor.check_columns(assignments)?;
+            restrictor.check_columns(selection)?;
+            if let Some(returning) = returning {
+                restrictor.restrict_projection(returning)?;
+            }
+        }
+        Statement::Delete { from, using, selection, returning, .. } => {
+            let mut tables = restrictor.scope_tables(from)?;
+            if let Some(using) = using {
+                tables.extend(restrictor.scope_tables(using)?);
+            }
+            restrictor.scopes.push(tables);
+            restrictor.check_join_constraints(from)?;
+            restrictor.check_join_constraints(using.as_deref_mut().unwrap_or_default())?;
+            restrictor.check_columns(selection)?;
+            if let Some(returning) = returning {
+                restrictor.restrict_projection(returning)?;
+            }
+        }
+        _ => {}
+    }
+
+    Ok(restrictor.modified)
+}
+
+/// Walks a statement, keeping each query to the columns the session may
+/// read
+///
+/// Like `QueryRewriter`, relations are walked by hand and the expressions
+/// of each query are searched for subqueries, here with a `ColumnChecker`,
+/// so that every column reference is resolved against the tables of the
+/// query it appears in and those of the queries enclosing it.
+struct ColumnRestrictor<'a> {
+    restricted: &'a HashMap<String, ReadableColumns>,
+    /// Names of the common table expressions in scope, which shadow tables
+    ctes: Vec<String>,
+    /// The tables read by each query enclosing the current expression,
+    /// innermost last
+    scopes: Vec<Vec<ScopedTable<'a>>>,
+    modified: bool,
+}
+
+/// A relation read by a query
+struct ScopedTable<'a> {
+    /// What the relation's columns are qualified with, if anything
+    qualifier: Option<Vec<Ident>>,
+    /// The table's name and readable columns, if its columns are restricted
+    restricted: Option<(&'a String, &'a ReadableColumns)>,
+}
+
+impl ScopedTable<'_> {
+    /// Check whether a column qualifier refers to the relation
+    fn is_named(&self, qualifier: &[Ident]) -> bool {
+        match (&self.qualifier, qualifier.last()) {
+            (Some(own), Some(name)) => own.last().is_some_and(|own| own.value.eq_ignore_ascii_case(&name.value)),
+            _ => false,
+        }
+    }
+
+    /// Fail if the column is one the session may not read
+    fn check_column(&self, column: &Ident) -> Result<()> {
+        match self.restricted {
+            Some((table, columns)) if columns.is_forbidden(&column.value) => {
+                Err(Error::PermissionDenied(format!("SELECT on column {} of table {}", column.value, table)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Get the projection `*` stands for over the relation
+    fn wildcard(&self) -> Result<Vec<SelectItem>> {
+        let Some(qualifier) = &self.qualifier else {
+            return Err(Error::UnsupportedSql(
+                "* over a subquery without an alias can't be limited to readable columns".to_string(),
+            ));
+        };
+        Ok(match self.restricted {
+            Some((_, columns)) => columns
+                .permitted
+                .iter()
+                .map(|column| {
+                    let mut idents = qualifier.clone();
+                    idents.push(Ident::with_quote('"', column));
+                    SelectItem::UnnamedExpr(Expr::CompoundIdentifier(idents))
+                })
+                .collect(),
+            None => vec![SelectItem::QualifiedWildcard(
+                ObjectName(qualifier.clone()),
+                WildcardAdditionalOptions::default(),
+            )],
+        })
+    }
+}
+
+impl<'a> ColumnRestrictor<'a> {
+    fn restrict_query(&mut self, query: &mut Query) -> Result<()> {
+        let scope = self.ctes.len();
+        let result = self.restrict_query_in_scope(query);
+        self.ctes.truncate(scope);
+        result
+    }
+
+    fn restrict_query_in_scope(&mut self, query: &mut Query) -> Result<()> {
+        if let Some(with) = &mut query.with {
+            for cte in &mut with.cte_tables {
+                self.ctes.push(cte.alias.name.value.clone());
+                self.restrict_query(&mut cte.query)?;
+            }
+        }
+
+        match &mut *query.body {
+            // ORDER BY may refer to the columns of the tables a plain
+            // SELECT reads, rather than only to its result columns
+            SetExpr::Select(select) => {
+                let tables = self.scope_tables(&mut select.from)?;
+                self.scopes.push(tables);
+                let result = self
+                    .restrict_select(select)
+                    .and_then(|_| self.check_columns(&mut query.order_by));
+                self.scopes.pop();
+                result?;
+            }
+            body => {
+                self.restrict_set_expr(body)?;
+                self.check_columns(&mut query.order_by)?;
+            }
+        }
+        self.check_columns(&mut query.limit)?;
+        self.check_columns(&mut query.offset)
+    }
+
+    /// Check whether a relation name refers to a common table expression
+    fn is_cte(&self, name: &ObjectName) -> bool {
+        match name.0.as_slice() {
+            [ident] => self.ctes.iter().any(|cte| cte.eq_ignore_ascii_case(&ident.value)),
+            _ => false,
+        }
+    }
+
+    fn restrict_set_expr(&mut self, body: &mut SetExpr) -> Result<()> {
+        match body {
+            SetExpr::Select(select) => {
+                let tables = self.scope_tables(&mut select.from)?;
+                self.scopes.push(tables);
+                let result = self.restrict_select(select);
+                self.scopes.pop();
+                result
+            }
+            SetExpr::Query(query) => self.restrict_query(query),
+            SetExpr::SetOperation { left, right, .. } => {
+                self.restrict_set_expr(left)?;
+                self.restrict_set_expr(right)
+            }
+            SetExpr::Values(values) => self.check_columns(values),
+            SetExpr::Insert(_) | SetExpr::Update(_) | SetExpr::Table(_) => Ok(()),
+        }
+    }
+
+    /// Restrict a SELECT whose tables are the innermost scope
+    fn restrict_select(&mut self, select: &mut Select) -> Result<()> {
+        self.restrict_projection(&mut select.projection)?;
+        // The FROM clause was walked by `scope_tables`, apart from its join
+        // constraints
+        let mut from = std::mem::take(&mut select.from);
+        let result = self
+            .check_columns(select)
+            .and_then(|_| self.check_join_constraints(&mut from));
+        select.from = from;
+        result
+    }
+
+    /// Expand the wildcards of a projection over the innermost scope and
+    /// check the rest of it
+    fn restrict_projection(&mut self, projection: &mut Vec<SelectItem>) -> Result<()> {
+        let tables = self.scopes.last().map(Vec::as_slice).unwrap_or_default();
+        let mut expanded = Vec::with_capacity(projection.len());
+        for item in projection.drain(..) {
+            match item {
+                SelectItem::Wildcard(_) if tables.iter().any(|table| table.restricted.is_some()) => {
+                    for table in tables {
+                        expanded.extend(table.wildcard()?);
+                    }
+                    self.modified = true;
+                }
+                SelectItem::QualifiedWildcard(name, _)
+                    if tables.iter().any(|table| table.is_named(&name.0) && table.restricted.is_some()) =>
+                {
+                    for table in tables.iter().filter(|table| table.is_named(&name.0)) {
+                        expanded.extend(table.wildcard()?);
+                    }
+                    self.modified = true;
+                }
+                item => expanded.push(item),
+            }
+        }
+        *projection = expanded;
+        self.check_columns(projection)
+    }
+
+    /// Get the relations read by a FROM clause, restricting the queries of
+    /// its derived tables
+    fn scope_tables(&mut self, from: &mut [TableWithJoins]) -> Result<Vec<ScopedTable<'a>>> {
+        let mut tables = Vec::new();
+        for table_with_joins in from {
+            let relations = std::iter::once(&mut table_with_joins.relation)
+                .chain(table_with_joins.joins.iter_mut().map(|join| &mut join.relation));
+            for relation in relations {
+                match relation {
+                    TableFactor::Table { name, alias, .. } => tables.push(self.scoped_table(name, alias)),
+                    TableFactor::Derived { subquery, alias, .. } => {
+                        self.restrict_query(subquery)?;
+                        tables.push(ScopedTable {
+                            qualifier: alias.as_ref().map(|alias| vec![alias.name.clone()]),
+                            restricted: None,
+                        });
+                    }
+                    TableFactor::NestedJoin { table_with_joins, .. } => {
+                        tables.extend(self.scope_tables(std::slice::from_mut(table_with_joins))?);
+                    }
+                    TableFactor::TableFunction { expr, alias } => {
+                        self.check_columns(expr)?;
+                        tables.push(ScopedTable {
+                            qualifier: alias.as_ref().map(|alias| vec![alias.name.clone()]),
+                            restricted: None,
+                        });
+                    }
+                    _ => tables.push(ScopedTable {
+                        qualifier: None,
+                        restricted: None,
+                    }),
+                }
+            }
+        }
+        Ok(tables)
+    }
+
+    fn scoped_table(&self, name: &ObjectName, alias: &Option<TableAlias>) -> ScopedTable<'a> {
+        let restricted = match name.0.last() {
+            Some(_) if self.is_cte(name) => None,
+            Some(ident) => self.restricted.get_key_value(&ident.value),
+            None => None,
+        };
+        ScopedTable {
+            qualifier: Some(table_qualifier(name, alias)),
+            restricted,
+        }
+    }
+
+    /// Check the constraints of the joins in a FROM clause
+    ///
+    /// `USING` reads the columns it names from the tables on both sides, and
+    /// `NATURAL` every column they share. Which columns those are can't be
+    /// told without the columns of every table joined, so a `NATURAL` join
+    /// is denied when the innermost scope has a table with forbidden columns.
+    fn check_join_constraints(&mut self, from: &mut [TableWithJoins]) -> Result<()> {
+        for table_with_joins in from {
+            if let TableFactor::NestedJoin { table_with_joins, .. } = &mut table_with_joins.relation {
+                self.check_join_constraints(std::slice::from_mut(table_with_joins))?;
+            }
+            for join in &mut table_with_joins.joins {
+                if let TableFactor::NestedJoin { table_with_joins, .. } = &mut join.relation {
+                    self.check_join_constraints(std::slice::from_mut(table_with_joins))?;
+                }
+                match join_constraint(&join.join_operator) {
+                    Some(JoinConstraint::Using(columns)) => {
+                        for column in columns {
+                            self.check_reference(&Expr::Identifier(column.clone()))?;
+                        }
+                    }
+                    Some(JoinConstraint::Natural) => {
+                        let restricted = self.scopes.last().into_iter().flatten().find_map(|table| {
+                            table.restricted.filter(|(_, columns)| !columns.forbidden.is_empty())
+                        });
+                        if let Some((table, _)) = restricted {
+                            return Err(Error::PermissionDenied(format!(
+                                "NATURAL JOIN with table {}, only some of whose columns can be selected",
+                                table
+                            )));
+                        }
+                    }
+                    _ => {}
+                }
+                self.check_columns(&mut join.join_operator)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Fail if a column reference may name a column the session can't read
+    fn check_reference(&self, expr: &Expr) -> Result<()> {
+        match expr {
+            Expr::Identifier(column) => {
+                for table in self.scopes.iter().flatten() {
+                    table.check_column(column)?;
+                }
+                Ok(())
+            }
+            Expr::CompoundIdentifier(idents) if idents.len() > 1 => {
+                let (column, qualifier) = idents.split_last().expect("the identifier has several parts");
+                // The innermost relation with the qualifier is the one meant
+                match self.scopes.iter().rev().find_map(|scope| scope.iter().find(|table| table.is_named(qualifier))) {
+                    Some(table) => table.check_column(column),
+                    None => Ok(()),
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Check the column references in the expressions of a node, restricting
+    /// the outermost subqueries found in them
+    fn check_columns<V: VisitMut>(&mut self, node: &mut V) -> Result<()> {
+        let mut visitor = ColumnChecker {
+            restrictor: self,
+            depth: 0,
+        };
+        match node.visit(&mut visitor) {
+            ControlFlow::Break(e) => Err(e),
+            ControlFlow::Continue(()) => Ok(()),
+        }
+    }
+}
+
+/// Checks the column references of an expression and restricts each of its
+/// outermost subqueries as a query
+///
+/// References within subqueries are left to the restriction of the
+/// subquery, which resolves them against the subquery's own tables first.
+struct ColumnChecker<'r, 'a> {
+    restrictor: &'r mut ColumnRestrictor<'a>,
+    depth: usize,
+}
+
+impl VisitorMut for ColumnChecker<'_, '_> {
+    type Break = Error;
+
+    fn pre_visit_expr(&mut self, expr: &mut Expr) -> ControlFlow<Self::Break> {
+        if is_subquery(expr) {
+            self.depth += 1;
+        } else if self.depth == 0 {
+            if let Err(e) = self.restrictor.check_reference(expr) {
+                return ControlFlow::Break(e);
+            }
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn post_visit_expr(&mut self, expr: &mut Expr) -> ControlFlow<Self::Break> {
+        if !is_subquery(expr) {
+            return ControlFlow::Continue(());
+        }
+        self.depth -= 1;
+        if self.depth > 0 {
+            return ControlFlow::Continue(());
+        }
+
+        let result = match expr {
+            Expr::Subquery(query) | Expr::ArraySubquery(query) | Expr::Exists { subquery: query, .. } => {
+                self.restrictor.restrict_query(query)
+            }
+            // The tested expression belongs to the enclosing query
+            Expr::InSubquery { expr, subquery, .. } => self
+                .restrictor
+                .restrict_query(subquery)
+                .and_then(|_| self.restrictor.check_columns(expr.as_mut())),
+            _ => unreachable!(),
+        };
+        match result {
+            Ok(()) => ControlFlow::Continue(()),
+            Err(e) => ControlFlow::Break(e),
+        }
+    }
+}
+
+/// Build `(SELECT * FROM name WHERE condition) AS alias`
+///
+/// Without an alias the derived table is named after the table, so the rest
+/// of the query can keep referring to it by name. When the table has masked
+/// columns, `*` is replaced with its columns in order, each masked column
+/// being replaced with its mask.
+fn filtered_table(
+    name: ObjectName,
+    alias: Option<TableAlias>,
+    condition: Option<Expr>,
+    masks: Option<&[MaskedColumn]>,
+) -> TableFactor {
+    let alias = alias.unwrap_or_else(|| TableAlias {
+        name: name.0.last().cloned().unwrap_or_else(|| Ident::new("")),
+        columns: Vec::new(),
+    });
+    let relation = TableFactor::Table {
+        name,
+        alias: None,
+        args: None,
+        with_hints: Vec::new(),
+    };
+    let projection = match masks {
+        Some(columns) => columns
+            .iter()
+            .map(|column| {
+                let name = Ident::with_quote('"', &column.name);
+                match &column.mask {
+                    Some(mask) => SelectItem::ExprWithAlias { expr: mask.clone(), alias: name },
+                    None => SelectItem::UnnamedExpr(Expr::Identifier(name)),
+                }
+            })
+            .collect(),
+        None => vec![SelectItem::Wildcard(WildcardAdditionalOptions::default())],
+    };
+    TableFactor::Derived {
+        lateral: false,
+        subquery: Box::new(select_query(
+            projection,
+            vec![TableWithJoins {
+                relation,
+                joins: Vec::new(),
+            }],
+            condition,
+        )),
+        alias: Some(alias),
+    }
+}
+
+/// Build `SELECT projection [FROM from] [WHERE selection]`
+pub fn select_query(projection: Vec<SelectItem>, from: Vec<TableWithJoins>, selection: Option<Expr>) -> Query {
+    let select = Select {
+        distinct: None,
+        top: None,
+        projection,
+        into: None,
+        from,
+        lateral_views: Vec::new(),
+        selection,
+        group_by: Vec::new(),
+        cluster_by: Vec::new(),
+        distribute_by: Vec::new(),
+        sort_by: Vec::new(),
+        having: None,
+        named_window: Vec::new(),
+        qualify: None,
+    };
+    Query {
+        with: None,
+        body: Box::new(SetExpr::Select(Box::new(select))),
+        order_by: Vec::new(),
+        limit: None,
+        offset: None,
+        fetch: None,
+        locks: Vec::new(),
+    }
+}
+
+/// Qualify the column references of a policy expression
+///
+/// Bare columns become `qualifier.column`, and columns qualified with the
+/// policy's own table name are requalified, e.g. `users.id` becomes `u.id`
+/// when the table is aliased as `u`. Columns inside subqueries are left alone
+/// as they resolve against the subquery's own tables first.
+pub fn qualify_columns(expr: &mut Expr, table_name: &str, qualifier: &[Ident]) {
+    let mut visitor = ColumnQualifier {
+        table_name,
+        qualifier,
+        subquery_depth: 0,
+    };
+    let _ = expr.visit(&mut visitor);
+}
+
+struct ColumnQualifier<'a> {
+    table_name: &'a str,
+    qualifier: &'a [Ident],
+    subquery_depth: usize,
+}
+
+impl VisitorMut for ColumnQualifier<'_> {
+    type Break = ();
+
+    fn pre_visit_expr(&mut self, expr: &mut Expr) -> ControlFlow<Self::Break> {
+        if is_subquery(expr) {
+            self.subquery_depth += 1;
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn post_visit_expr(&mut self, expr: &mut Expr) -> ControlFlow<Self::Break> {
+        if is_subquery(expr) {
+            self.subquery_depth -= 1;
+        } else if self.subquery_depth == 0 {
+            match expr {
+                Expr::Identifier(column) => {
+                    let mut idents = self.qualifier.to_vec();
+                    idents.push(column.clone());
+                    *expr = Expr::CompoundIdentifier(idents);
+                }
+                Expr::CompoundIdentifier(idents)
+                    if idents.len() == 2 && idents[0].value.eq_ignore_ascii_case(self.table_name) =>
+                {
+                    let column = idents.pop().unwrap();
+                    *idents = self.qualifier.to_vec();
+                    idents.push(column);
+                }
+                _ => {}
+            }
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// Check whether an expression holds a subquery with its own scope
+fn is_subquery(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::Subquery(_) | Expr::Exists { .. } | Expr::InSubquery { .. } | Expr::ArraySubquery(_)
+    )
+}
+
+/// Combine the USING expressions of a table's policies into one predicate
+///
+/// Builds `(p1 OR p2 ...) AND r1 AND r2 ...` from the permissive policies
+/// `p` and restrictive policies `r`. As in Postgres, a table without any
+/// permissive USING expression yields `false`, so restrictive policies alone
+/// never grant access.
+pub fn combine_policy_expressions(policies: &[Policy]) -> Result<Expr> {
+    combine_policies(policies, |policy| policy.using_expr.as_ref())
+}
+
+/// Combine the WITH CHECK expressions of a table's policies into one predicate
+///
+/// Policies without a WITH CHECK expression check their USING expression
+/// instead, as in Postgres. The expressions combine like
+/// `combine_policy_expressions`.
+pub fn combine_policy_checks(policies: &[Policy]) -> Result<Expr> {
+    combine_policies(policies, |policy| policy.check_expr.as_ref().or(policy.using_expr.as_ref()))
+}
+
+fn combine_policies(policies: &[Policy], expression: impl Fn(&Policy) -> Option<&String>) -> Result<Expr> {
+    let mut permissive: Option<Expr> = None;
+    let mut restrictive = Vec::new();
+
+    for policy in policies {
+        let Some(policy_expr) = expression(policy) else {
+            continue;
+        };
+        let expr = Expr::Nested(Box::new(parse_policy_expression(policy_expr)?));
+
+        match policy.kind {
+            PolicyKind::Permissive => {
+                permissive = Some(match permissive {
+                    Some(left) => Expr::BinaryOp {
+                        left: Box::new(left),
+                        op: BinaryOperator::Or,
+                        right: Box::new(expr),
+                    },
+                    None => expr,
+                });
+            }
+            PolicyKind::Restrictive => restrictive.push(expr),
+        }
+    }
+
+    let mut condition = permissive.unwrap_or(Expr::Value(Value::Boolean(false)));
+    if !restrictive.is_empty() {
+        condition = Expr::Nested(Box::new(condition));
+    }
+
+    for expr in restrictive {
+        condition = Expr::BinaryOp {
+            left: Box::new(condition),
+            op: BinaryOperator::And,
+            right: Box::new(expr),
+        };
+    }
+
+    Ok(condition)
+}
+
+/// AND a policy condition onto a WHERE clause
+fn add_condition(selection: &mut Option<Expr>, policy_condition: Expr) {
+    let policy_condition = Expr::Nested(Box::new(policy_condition));
     
-    // If there's an existing WHERE clause, AND it with the policy
-    if let Some(where_clause) = &select.selection {
+    // If there's an existing WHERE clause, AND it with the policy. Both sides are
+    // parenthesized so that an OR in either one cannot escape the conjunction.
+    if let Some(where_clause) = selection.take() {
         let new_where = Expr::BinaryOp {
-            left: Box::new(where_clause.clone()),
-            op: sqlparser::ast::BinaryOperator::And,
+            left: Box::new(Expr::Nested(Box::new(where_clause))),
+            op: BinaryOperator::And,
             right: Box::new(policy_condition),
         };
-        select.selection = Some(new_where);
+        *selection = Some(new_where);
     } else {
         // Otherwise, set the policy as the WHERE clause
-        select.selection = Some(policy_condition);
+        *selection = Some(policy_condition);
     }
-
-    Ok(())
 }
 
 /// Parse a policy expression string into an Expr AST
-fn parse_policy_expression(expr_str: &str) -> Result<Expr> {
-    // For the prototype, we'll handle simple equality comparisons
-    // Format: "column_name = value"
-    let parts: Vec<&str> = expr_str.trim().split('=').collect();
-    if parts.len() != 2 {
+///
+/// Any expression SQLite can evaluate in a WHERE clause is accepted, e.g.
+/// `tenant_id = 100 AND (is_public OR owner_id = 7)`.
+pub fn parse_policy_expression(expr_str: &str) -> Result<Expr> {
+    let dialect = SQLiteDialect {};
+    let mut parser = Parser::new(&dialect).try_with_sql(expr_str)?;
+    let expr = parser.parse_expr()?;
+
+    // The whole string must be a single expression
+    let trailing = parser.peek_token();
+    if trailing.token != Token::EOF {
+        return Err(Error::Policy(format!(
+            "Unexpected {} after policy expression: {}",
+            trailing, expr_str
+        )));
+    }
+
+    check_policy_placeholders(&expr)?;
+
+    Ok(expr)
+}
+
+/// Ensure a policy expression only uses named placeholders
+///
+/// Policies read their parameters from the session context by name, so
+/// positional parameters would be confused with the caller's own.
+pub fn check_policy_placeholders(expr: &Expr) -> Result<()> {
+    let flow = visit_expressions(expr, |e| match e {
+        Expr::Value(Value::Placeholder(placeholder)) if placeholder.starts_with('?') => {
+            ControlFlow::Break(placeholder.clone())
+        }
+        _ => ControlFlow::Continue(()),
+    });
+    if let ControlFlow::Break(placeholder) = flow {
         return Err(Error::Policy(format!(
-            "Only simple equality expressions are supported, got: {}", 
-            expr_str
+            "Policy expressions only support named parameters such as :tenant_id, got: {}",
+            placeholder
         )));
     }
 
-    let column = parts[0].trim();
-    let value = parts[1].trim();
+    Ok(())
+}
+
+/// Renumber every placeholder in a SQL string as an explicit `?NNN`
+///
+/// Policies are spliced into the caller's statement, which would shift the
+/// index SQLite assigns to anonymous `?` and named parameters. Numbering them
+/// up front, following SQLite's own assignment rules, keeps the caller's
+/// parameters stable however the statement is rewritten. Returns the new SQL
+/// and, for each parameter index, the name of the named placeholder bound to
+/// it (including its prefix, e.g. `:id`).
+pub fn number_placeholders(sql: &str) -> Result<(String, Vec<Option<String>>)> {
+    let dialect = SQLiteDialect {};
+    let tokens = Tokenizer::new(&dialect, sql).tokenize_with_location()?;
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(sql.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+
+    let mut names: Vec<Option<String>> = Vec::new();
+    let mut numbered = String::with_capacity(sql.len());
+    let mut copied = 0;
+
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some(token) = tokens.next() {
+        // `$name` and `?NNN` are single tokens, while `:name` and `@name` are a
+        // prefix token directly followed by a word
+        let placeholder = match &token.token {
+            Token::Placeholder(placeholder) => placeholder.clone(),
+            Token::Colon | Token::AtSign => match tokens.peek().map(|next| &next.token) {
+                Some(word @ Token::Word(_)) => {
+                    let placeholder = format!("{}{}", token.token, word);
+                    tokens.next();
+                    placeholder
+                }
+                _ => continue,
+            },
+            _ => continue,
+        };
+        let index = if placeholder == "?" {
+            names.push(None);
+            names.len()
+        } else if let Some(number) = placeholder.strip_prefix('?') {
+            let index: usize = number.parse().map_err(|_| {
+                Error::UnsupportedSql(format!("Invalid parameter {}", placeholder))
+            })?;
+            if names.len() < index {
+                names.resize(index, None);
+            }
+            index
+        } else {
+            match names.iter().position(|name| name.as_ref() == Some(&placeholder)) {
+                Some(position) => position + 1,
+                None => {
+                    names.push(Some(placeholder.clone()));
+                    names.len()
+                }
+            }
+        };
+
+        // Token locations are 1-based lines and character columns
+        let line_start = line_starts[token.location.line as usize - 1];
+        let start = sql[line_start..]
+            .char_indices()
+            .nth(token.location.column as usize - 1)
+            .map_or(sql.len(), |(offset, _)| line_start + offset);
 
-    // Try to parse value as a number first
-    if let Ok(num) = value.parse::<i64>() {
-        return Ok(Expr::BinaryOp {
-            left: Box::new(Expr::Identifier(sqlparser::ast::Ident::new(column))),
-            op: sqlparser::ast::BinaryOperator::Eq,
-            right: Box::new(Expr::Value(Value::Number(num.to_string(), false))),
-        });
+        numbered.push_str(&sql[copied..start]);
+        numbered.push_str(&format!("?{}", index));
+        copied = start + placeholder.len();
+    }
+    numbered.push_str(&sql[copied..]);
+
+    Ok((numbered, names))
+}
+
+/// Get the highest `?NNN` parameter index used by a statement
+pub fn max_placeholder_index(statement: &Statement) -> usize {
+    let mut max_index = 0;
+    let _ = visit_expressions(statement, |expr| {
+        if let Expr::Value(Value::Placeholder(placeholder)) = expr {
+            if let Some(index) = placeholder.strip_prefix('?').and_then(|index| index.parse().ok()) {
+                max_index = max_index.max(index);
+            }
+        }
+        ControlFlow::<()>::Continue(())
+    });
+    max_index
+}
+
+/// Bind session values into a statement as query parameters
+///
+/// Calls to `current_user_id()`, `current_role()` and `current_setting('key')`,
+/// as well as the named placeholders used by policies (`:key`), are replaced
+/// by numbered parameters starting at `first_index`. The values are read from
+/// the session context and returned in parameter order, so the rewritten SQL
+/// is identical for every user and never contains context values as literals.
+///
+/// libSQL does not expose user-defined SQL functions, which is why the
+/// session functions are resolved while rewriting the statement.
+pub fn bind_session_values(
+    statement: &mut Statement,
+    context: &SessionContext,
+    first_index: usize,
+) -> Result<Vec<libsql::Value>> {
+    let mut keys: Vec<String> = Vec::new();
+
+    let flow = visit_expressions_mut(statement, |expr| {
+        let key = match expr {
+            Expr::Function(function) => {
+                let [name] = function.name.0.as_slice() else {
+                    return ControlFlow::Continue(());
+                };
+                match name.value.to_lowercase().as_str() {
+                    "current_user_id" if function.args.is_empty() => USER_ID_KEY.to_string(),
+                    "current_role" if function.args.is_empty() => ROLE_KEY.to_string(),
+                    "current_setting" => match setting_name(&function.args) {
+                        Some(key) => key,
+                        None => {
+                            return ControlFlow::Break(Error::UnsupportedSql(format!(
+                                "current_setting expects a string literal setting name, got: {}",
+                                function
+                            )))
+                        }
+                    },
+                    _ => return ControlFlow::Continue(()),
+                }
+            }
+            // The caller's parameters have all been numbered, so any named
+            // placeholder left in the statement comes from a policy
+            Expr::Value(Value::Placeholder(placeholder)) if !placeholder.starts_with('?') => {
+                placeholder[1..].to_string()
+            }
+            _ => return ControlFlow::Continue(()),
+        };
+
+        let position = match keys.iter().position(|k| *k == key) {
+            Some(position) => position,
+            None => {
+                keys.push(key);
+                keys.len() - 1
+            }
+        };
+        *expr = Expr::Value(Value::Placeholder(format!("?{}", first_index + position)));
+        ControlFlow::Continue(())
+    });
+
+    if let ControlFlow::Break(e) = flow {
+        return Err(e);
+    }
+
+    Ok(keys
+        .iter()
+        .map(|key| context.get(key).cloned().unwrap_or(libsql::Value::Null))
+        .collect())
+}
+
+/// Extract the setting name from `current_setting('name' [, missing_ok])`
+fn setting_name(args: &[FunctionArg]) -> Option<String> {
+    match args {
+        [FunctionArg::Unnamed(FunctionArgExpr::Expr(Expr::Value(Value::SingleQuotedString(name))))]
+        | [FunctionArg::Unnamed(FunctionArgExpr::Expr(Expr::Value(Value::SingleQuotedString(name)))), _] => {
+            Some(name.clone())
+        }
+        _ => None,
+    }
+}
+
+/// Get the constraint of a join, if its kind of join has one
+fn join_constraint(operator: &JoinOperator) -> Option<&JoinConstraint> {
+    match operator {
+        JoinOperator::Inner(constraint)
+        | JoinOperator::LeftOuter(constraint)
+        | JoinOperator::RightOuter(constraint)
+        | JoinOperator::FullOuter(constraint)
+        | JoinOperator::LeftSemi(constraint)
+        | JoinOperator::RightSemi(constraint)
+        | JoinOperator::LeftAnti(constraint)
+        | JoinOperator::RightAnti(constraint) => Some(constraint),
+        JoinOperator::CrossJoin | JoinOperator::CrossApply | JoinOperator::OuterApply => None,
     }
-    
-    // Otherwise, treat as string
-    let value_str = value.trim_matches('\'').trim_matches('"');
-    Ok(Expr::BinaryOp {
-        left: Box::new(Expr::Identifier(sqlparser::ast::Ident::new(column))),
-        op: sqlparser::ast::BinaryOperator::Eq,
-        right: Box::new(Expr::Value(Value::SingleQuotedString(value_str.to_string()))),
-    })
 }
 
 /// Compile an AST back to SQL
 pub fn compile_ast_to_sql(statement: &Statement) -> String {
-    statement.to_string()
-} 
\ No newline at end of file
+    match statement {
+        // Displayed as `START TRANSACTION`, which SQLite doesn't accept
+        Statement::StartTransaction { modes } if modes.is_empty() => "BEGIN".to_string(),
+        _ => statement.to_string(),
+    }
+}
\ No newline at end of file
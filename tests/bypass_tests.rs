@@ -0,0 +1,86 @@
+use libsql_rls::{Error, Result, RlsConnection};
+use libsql::{Database, params};
+
+async fn visible_ids(rls_conn: &RlsConnection) -> Result<Vec<i64>> {
+    let mut rows = rls_conn.query("SELECT id FROM documents ORDER BY id", params![]).await?;
+    let mut ids = Vec::new();
+    while let Some(row) = rows.next()? {
+        ids.push(row.get::<i64>(0)?);
+    }
+    Ok(ids)
+}
+
+#[tokio::test]
+async fn test_bypass_token_skips_policies() -> Result<()> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connect()?;
+    conn.execute("CREATE TABLE documents (id INTEGER PRIMARY KEY, tenant_id INTEGER NOT NULL)", params![]).await?;
+    conn.execute("INSERT INTO documents (id, tenant_id) VALUES (1, 100), (2, 200)", params![]).await?;
+
+    // Both wrappers share the connection, and so the in-memory database
+    let mut admin_conn = RlsConnection::new_initialized(conn.clone()).await?;
+    // Only admin connections issue tokens
+    assert!(matches!(admin_conn.issue_bypass_token("nightly-export"), Err(Error::PermissionDenied(_))));
+    admin_conn.set_admin(true);
+    admin_conn.execute("CREATE POLICY tenant ON documents USING (tenant_id = 100)", params![]).await?;
+    assert_eq!(visible_ids(&admin_conn).await?, vec![1]);
+
+    let token = admin_conn.issue_bypass_token("nightly-export")?;
+    let bypass_conn = RlsConnection::with_bypass(conn, token);
+    assert_eq!(visible_ids(&bypass_conn).await?, vec![1, 2]);
+    bypass_conn.execute("UPDATE documents SET tenant_id = 300 WHERE id = 2", params![]).await?;
+
+    // Session functions still resolve
+    bypass_conn.set_user(7);
+    let mut rows = bypass_conn.query("SELECT current_user_id()", params![]).await?;
+    assert_eq!(rows.next()?.unwrap().get::<i64>(0)?, 7);
+
+    // The catalog is still off limits
+    assert!(matches!(
+        bypass_conn.query("SELECT * FROM _rls_policies", params![]).await,
+        Err(Error::PermissionDenied(_))
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bypassrls_roles_and_table_owners() -> Result<()> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connect()?;
+
+    conn.execute("CREATE TABLE documents (id INTEGER PRIMARY KEY, tenant_id INTEGER NOT NULL)", params![]).await?;
+    conn.execute("INSERT INTO documents (id, tenant_id) VALUES (1, 100), (2, 200)", params![]).await?;
+
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+    rls_conn.set_admin(true);
+    for sql in [
+        "CREATE ROLE app",
+        "CREATE ROLE jobs WITH BYPASSRLS",
+        "CREATE ROLE owners",
+        "CREATE ROLE maintainer",
+        "GRANT owners TO maintainer",
+        "CREATE POLICY tenant ON documents USING (tenant_id = 100)",
+    ] {
+        rls_conn.execute(sql, params![]).await?;
+    }
+
+    rls_conn.set_role("jobs");
+    assert_eq!(visible_ids(&rls_conn).await?, vec![1, 2]);
+    rls_conn.execute("ALTER ROLE jobs NOBYPASSRLS", params![]).await?;
+    assert_eq!(visible_ids(&rls_conn).await?, vec![1]);
+
+    // Owners, and members of the owning role, are exempt...
+    rls_conn.execute("ALTER TABLE documents OWNER TO owners", params![]).await?;
+    rls_conn.set_role("maintainer");
+    assert_eq!(visible_ids(&rls_conn).await?, vec![1, 2]);
+    rls_conn.set_role("app");
+    assert_eq!(visible_ids(&rls_conn).await?, vec![1]);
+
+    // ...unless row level security is forced
+    rls_conn.execute("ALTER TABLE documents FORCE ROW LEVEL SECURITY", params![]).await?;
+    rls_conn.set_role("owners");
+    assert_eq!(visible_ids(&rls_conn).await?, vec![1]);
+
+    Ok(())
+}
@@ -0,0 +1,104 @@
+use libsql_rls::{Error, Result, RewriteStrategy, RlsConnection};
+use libsql::{Database, params};
+
+async fn strings(rls_conn: &RlsConnection, sql: &str) -> Result<Vec<String>> {
+    let mut rows = rls_conn.query(sql, params![]).await?;
+    let mut values = Vec::new();
+    while let Some(row) = rows.next()? {
+        values.push(row.get::<String>(0)?);
+    }
+    Ok(values)
+}
+
+#[tokio::test]
+async fn test_masks_apply_to_every_reference() -> Result<()> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connect()?;
+
+    conn.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, email TEXT NOT NULL)", params![]).await?;
+    conn.execute("CREATE TABLE tickets (id INTEGER PRIMARY KEY, user_id INTEGER NOT NULL)", params![]).await?;
+    conn.execute(
+        "INSERT INTO users (id, email) VALUES (1, 'ann@example.com'), (2, 'bob@example.com'), (3, 'carl@example.com')",
+        params![],
+    ).await?;
+    conn.execute("INSERT INTO tickets (id, user_id) VALUES (10, 1)", params![]).await?;
+
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+    rls_conn.set_admin(true);
+    for sql in [
+        "CREATE ROLE support",
+        "CREATE MASKING POLICY email_mask ON users (email) TO support USING (
+            substr(email, 1, 1) || '***@' || substr(email, instr(email, '@') + 1)
+        )",
+        // Policies see the actual values
+        "CREATE POLICY not_carl ON users USING (email <> 'carl@example.com')",
+    ] {
+        rls_conn.execute(sql, params![]).await?;
+    }
+    rls_conn.set_admin(false);
+
+    assert_eq!(
+        strings(&rls_conn, "SELECT email FROM users ORDER BY id").await?,
+        vec!["ann@example.com", "bob@example.com"]
+    );
+
+    rls_conn.set_role("support");
+    for strategy in [RewriteStrategy::DerivedTable, RewriteStrategy::WhereClause] {
+        rls_conn.set_rewrite_strategy(strategy);
+        assert_eq!(
+            strings(&rls_conn, "SELECT email FROM users ORDER BY id").await?,
+            vec!["a***@example.com", "b***@example.com"]
+        );
+        for sql in [
+            "SELECT upper(u.email) FROM USERS AS u JOIN tickets AS t ON t.user_id = u.id",
+            "SELECT (SELECT email FROM users WHERE id = t.user_id) FROM tickets AS t",
+            "SELECT email FROM (SELECT * FROM users) WHERE id = 1",
+            "SELECT email FROM users WHERE email LIKE 'a***%'",
+        ] {
+            let masked = strings(&rls_conn, sql).await?;
+            assert!(masked.iter().all(|email| email.to_lowercase() == "a***@example.com"), "{sql}: {masked:?}");
+            assert_eq!(masked.len(), 1, "{sql}");
+        }
+        // Filtering on a masked column can't recover the actual value
+        let found = strings(&rls_conn, "SELECT email FROM users WHERE email = 'ann@example.com'").await?;
+        assert!(found.is_empty());
+    }
+
+    // Data-modifying statements read the masks of their target too
+    let mut rows = rls_conn.query("UPDATE users SET id = id WHERE id = 2 RETURNING email", params![]).await?;
+    assert_eq!(rows.next()?.expect("bob was updated").get::<String>(0)?, "b***@example.com");
+    drop(rows);
+    assert_eq!(rls_conn.execute("DELETE FROM users WHERE email = 'ann@example.com'", params![]).await?, 0);
+
+    // Assignments reading a masked column would write the mask over the
+    // actual value
+    for sql in [
+        "UPDATE users SET email = lower(email) WHERE id = 1",
+        "UPDATE users AS u SET email = u.email || '' WHERE id = 1",
+        "INSERT INTO users (id, email) VALUES (1, 'x') ON CONFLICT (id) DO UPDATE SET email = upper(email)",
+    ] {
+        let result = rls_conn.execute(sql, params![]).await;
+        assert!(matches!(result, Err(Error::PermissionDenied(_))), "{sql}: {result:?}");
+    }
+    rls_conn.execute("UPDATE users SET email = 'ann@example.org' WHERE email LIKE 'a%'", params![]).await?;
+
+    rls_conn.set_admin(true);
+    for sql in [
+        "CREATE MASKING POLICY other ON users (EMAIL) USING ('hidden')",
+        "CREATE MASKING POLICY other ON tickets (id) TO nobody USING (0)",
+        "DROP ROLE support",
+    ] {
+        let result = rls_conn.execute(sql, params![]).await;
+        assert!(matches!(result, Err(Error::Policy(_))), "{sql}: {result:?}");
+    }
+    rls_conn.execute("DROP MASKING POLICY email_mask ON users", params![]).await?;
+    assert_eq!(
+        strings(&rls_conn, "SELECT email FROM users WHERE id = 1").await?,
+        vec!["ann@example.org"]
+    );
+    let result = rls_conn.execute("DROP MASKING POLICY email_mask ON users", params![]).await;
+    assert!(matches!(result, Err(Error::Policy(_))));
+    rls_conn.execute("DROP MASKING POLICY IF EXISTS email_mask ON users", params![]).await?;
+
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use libsql_rls::{Result, RlsConnection};
+use libsql_rls::{Error, Result, RlsConnection};
 use libsql::{Database, params};
 
 #[tokio::test]
@@ -9,7 +9,8 @@ async fn test_create_policy_parsing() -> Result<()> {
     
     // Wrap the connection with RLS and initialize it
     // This will automatically create the policy table
-    let rls_conn = RlsConnection::new_initialized(conn).await?;
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+    rls_conn.set_admin(true);
     
     // Test a basic CREATE POLICY statement directly through the wrapped connection
     let policy_sql = "CREATE POLICY user_policy ON users USING (user_id = current_user_id())";
@@ -73,4 +74,158 @@ async fn test_create_policy_parsing() -> Result<()> {
     assert!(check_expr.contains("approved"));
     
     Ok(())
-} 
\ No newline at end of file
+} 
+#[tokio::test]
+async fn test_create_policy_full_syntax() -> Result<()> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connect()?;
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+    rls_conn.set_admin(true);
+
+    // Nested parentheses in USING must not be truncated at the first ')'
+    rls_conn.execute(
+        r#"CREATE POLICY "Tenant Policy" ON "Documents"
+           AS PERMISSIVE
+           FOR UPDATE
+           TO PUBLIC
+           USING (tenant_id = abs(coalesce(owner_id, (1 + 2))))
+           WITH CHECK ((status = 'draft') OR (status = 'review'));"#,
+        params![],
+    ).await?;
+
+    let mut rows = rls_conn.query(
+        "SELECT name, table_name, command, using_expr, check_expr FROM _rls_policies",
+        params![],
+    ).await?;
+
+    let row = rows.next()?.unwrap();
+    assert_eq!(row.get::<String>(0)?, "Tenant Policy");
+    assert_eq!(row.get::<String>(1)?, "Documents");
+    assert_eq!(row.get::<String>(2)?, "UPDATE");
+    assert_eq!(row.get::<String>(3)?, "tenant_id = abs(coalesce(owner_id, (1 + 2)))");
+    assert_eq!(row.get::<String>(4)?, "(status = 'draft') OR (status = 'review')");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_create_policy_errors() -> Result<()> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connect()?;
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+    rls_conn.set_admin(true);
+
+    let cases = [
+        ("CREATE POLICY p users USING (a = 1)", "Expected ON after policy name, found: users at Line: 1, Column 17"),
+        ("CREATE POLICY p ON users FOR MERGE", "Expected ALL, SELECT, INSERT, UPDATE or DELETE after FOR, found: MERGE at Line: 1, Column 30"),
+        ("CREATE POLICY p ON users USING (a = 1", "Expected ), found: EOF"),
+        ("CREATE POLICY p ON users\nUSING (a = 1) extra", "found: extra at Line: 2, Column 15"),
+    ];
+
+    for (sql, message) in cases {
+        match rls_conn.execute(sql, params![]).await {
+            Err(Error::Policy(actual)) => assert!(
+                actual.contains(message),
+                "expected {:?} to contain {:?}", actual, message
+            ),
+            other => panic!("expected a policy error for {:?}, got {:?}", sql, other),
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_drop_and_alter_policy() -> Result<()> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connect()?;
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+    rls_conn.set_admin(true);
+
+    rls_conn.execute(
+        "CREATE POLICY tenant ON users USING (tenant_id = 100) WITH CHECK (tenant_id = 100)",
+        params![],
+    ).await?;
+
+    rls_conn.execute("ALTER POLICY tenant ON users USING (tenant_id = 200)", params![]).await?;
+    rls_conn.execute("ALTER POLICY tenant ON users RENAME TO tenant_isolation", params![]).await?;
+
+    let mut rows = rls_conn.query(
+        "SELECT name, using_expr, check_expr FROM _rls_policies",
+        params![],
+    ).await?;
+    let row = rows.next()?.unwrap();
+    assert_eq!(row.get::<String>(0)?, "tenant_isolation");
+    assert_eq!(row.get::<String>(1)?, "tenant_id = 200");
+    // Clauses left out of ALTER POLICY keep their value
+    assert_eq!(row.get::<String>(2)?, "tenant_id = 100");
+
+    // Missing policies are an error unless IF EXISTS is given
+    assert!(matches!(
+        rls_conn.execute("DROP POLICY tenant ON users", params![]).await,
+        Err(Error::Policy(_))
+    ));
+    assert!(matches!(
+        rls_conn.execute("ALTER POLICY tenant ON users USING (true)", params![]).await,
+        Err(Error::Policy(_))
+    ));
+    assert_eq!(rls_conn.execute("DROP POLICY IF EXISTS tenant ON users", params![]).await?, 0);
+
+    assert_eq!(rls_conn.execute("DROP POLICY tenant_isolation ON users;", params![]).await?, 1);
+    let mut rows = rls_conn.query("SELECT COUNT(*) FROM _rls_policies", params![]).await?;
+    assert_eq!(rows.next()?.unwrap().get::<i64>(0)?, 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_catalog_requires_admin() -> Result<()> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connect()?;
+
+    conn.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, tenant_id INTEGER NOT NULL)", params![]).await?;
+    conn.execute("INSERT INTO users (id, tenant_id) VALUES (1, 200)", params![]).await?;
+
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+    rls_conn.set_admin(true);
+    rls_conn.execute("CREATE POLICY tenant ON users USING (tenant_id = 100)", params![]).await?;
+    rls_conn.set_admin(false);
+
+    for sql in [
+        "SELECT * FROM _rls_policies",
+        "UPDATE _rls_policies SET using_expr = '1=1'",
+        "DELETE FROM \"_RLS_POLICIES\"",
+        "SELECT * FROM users WHERE id IN (SELECT rowid FROM main._rls_tables)",
+        "DROP TABLE _rls_policies",
+        "CREATE POLICY everyone ON users USING (true)",
+        "DROP POLICY tenant ON users",
+        "ALTER TABLE users DISABLE ROW LEVEL SECURITY",
+    ] {
+        let result = rls_conn.execute(sql, params![]).await;
+        assert!(
+            matches!(result, Err(Error::PermissionDenied(_))),
+            "expected {sql} to be denied, got {result:?}"
+        );
+    }
+    // Denied even when unparseable SQL would otherwise pass through
+    rls_conn.set_strict(false);
+    assert!(matches!(
+        rls_conn.query("SELECT * FROM _rls_policies WHERE", params![]).await,
+        Err(Error::PermissionDenied(_))
+    ));
+
+    // SQLite doesn't nest comments, so it reads this as an UPDATE of the
+    // catalog, while the parser only sees `SELECT 1`
+    for strict in [true, false] {
+        rls_conn.set_strict(strict);
+        rls_conn.query("/* /* */ UPDATE _rls_policies SET using_expr = '1=1' -- */ SELECT 1", params![]).await?;
+    }
+    let mut rows = rls_conn.query("SELECT id FROM users", params![]).await?;
+    assert!(rows.next()?.is_none());
+
+    rls_conn.set_admin(true);
+    let mut rows = rls_conn.query("SELECT using_expr FROM _rls_policies", params![]).await?;
+    assert_eq!(rows.next()?.unwrap().get::<String>(0)?, "tenant_id = 100");
+
+    Ok(())
+}
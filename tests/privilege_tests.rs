@@ -0,0 +1,218 @@
+use libsql_rls::{Error, Result, RlsConnection};
+use libsql::{Database, params};
+
+async fn note_ids(rls_conn: &RlsConnection) -> Result<Vec<i64>> {
+    let mut rows = rls_conn.query("SELECT id FROM notes ORDER BY id", params![]).await?;
+    let mut ids = Vec::new();
+    while let Some(row) = rows.next()? {
+        ids.push(row.get::<i64>(0)?);
+    }
+    Ok(ids)
+}
+
+#[tokio::test]
+async fn test_privileges_are_checked_before_policies() -> Result<()> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connect()?;
+
+    conn.execute("CREATE TABLE notes (id INTEGER PRIMARY KEY, author_id INTEGER NOT NULL)", params![]).await?;
+    conn.execute("CREATE TABLE tags (id INTEGER PRIMARY KEY, name TEXT NOT NULL)", params![]).await?;
+    conn.execute("INSERT INTO notes (id, author_id) VALUES (1, 1), (2, 2)", params![]).await?;
+
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+    rls_conn.set_admin(true);
+    for sql in [
+        "CREATE ROLE authenticated",
+        "CREATE ROLE editor",
+        "CREATE ROLE owner",
+        "GRANT authenticated TO editor",
+        "GRANT SELECT, INSERT ON notes TO authenticated",
+        "GRANT UPDATE ON TABLE notes TO editor",
+        "ALTER TABLE notes OWNER TO owner",
+        "CREATE POLICY own ON notes USING (author_id = current_user_id())",
+    ] {
+        rls_conn.execute(sql, params![]).await?;
+    }
+    rls_conn.set_admin(false);
+
+    // Without a grant to PUBLIC, sessions without a role can't use the table
+    let result = rls_conn.query("SELECT id FROM notes", params![]).await;
+    assert!(matches!(result, Err(Error::PermissionDenied(_))));
+
+    // Granted statements still only see the rows the policies allow
+    rls_conn.set_role("authenticated");
+    rls_conn.set_user(1);
+    assert_eq!(note_ids(&rls_conn).await?, vec![1]);
+    rls_conn.execute("INSERT INTO notes (id, author_id) VALUES (3, 1)", params![]).await?;
+    for sql in ["DELETE FROM notes WHERE id = 1", "DELETE FROM NOTES", "UPDATE notes SET author_id = 1"] {
+        let result = rls_conn.execute(sql, params![]).await;
+        assert!(matches!(result, Err(Error::PermissionDenied(_))), "{sql}");
+    }
+
+    // Tables never named by a GRANT or REVOKE stay open to every role
+    rls_conn.execute("INSERT INTO tags (id, name) VALUES (1, 'todo')", params![]).await?;
+
+    // Editors inherit the privileges of authenticated
+    rls_conn.set_role("editor");
+    rls_conn.execute("UPDATE notes SET author_id = 1 WHERE id = 3", params![]).await?;
+
+    rls_conn.set_admin(true);
+    rls_conn.execute("REVOKE INSERT ON NOTES FROM authenticated", params![]).await?;
+    rls_conn.set_admin(false);
+    for sql in ["INSERT INTO notes (id, author_id) VALUES (4, 1)", "INSERT INTO \"Notes\" (id, author_id) VALUES (4, 1)"] {
+        let result = rls_conn.execute(sql, params![]).await;
+        assert!(matches!(result, Err(Error::PermissionDenied(_))), "{sql}");
+    }
+
+    // The owner holds every privilege and is exempt from the policies
+    rls_conn.set_role("owner");
+    assert_eq!(note_ids(&rls_conn).await?, vec![1, 2, 3]);
+    rls_conn.execute("DELETE FROM notes WHERE id = 3", params![]).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_privileges_of_read_tables_and_grant_errors() -> Result<()> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connect()?;
+
+    conn.execute("CREATE TABLE notes (id INTEGER PRIMARY KEY, body TEXT)", params![]).await?;
+    conn.execute("CREATE TABLE drafts (id INTEGER PRIMARY KEY, body TEXT)", params![]).await?;
+    conn.execute("INSERT INTO drafts (id, body) VALUES (1, 'hello')", params![]).await?;
+
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+    rls_conn.set_admin(true);
+    for sql in [
+        "CREATE ROLE writer",
+        "CREATE ROLE keeper",
+        "GRANT INSERT, DELETE ON notes TO writer",
+        "ALTER TABLE notes OWNER TO keeper",
+        "REVOKE ALL ON drafts FROM PUBLIC",
+    ] {
+        rls_conn.execute(sql, params![]).await?;
+    }
+    rls_conn.set_admin(false);
+    rls_conn.set_role("writer");
+
+    // Writing a table doesn't grant reading it, nor the tables copied from
+    rls_conn.execute("INSERT INTO notes (id, body) VALUES (1, 'hi')", params![]).await?;
+    rls_conn.execute("DELETE FROM notes", params![]).await?;
+    for sql in [
+        "DELETE FROM notes WHERE id = 1",
+        "INSERT INTO notes (id, body) VALUES (2, 'hi') RETURNING id",
+        "INSERT INTO notes (id, body) SELECT id, body FROM drafts",
+        // Only the owner can drop or index the table
+        "DROP TABLE notes",
+        "CREATE INDEX notes_body ON notes (body)",
+        "DROP TABLE IF EXISTS drafts",
+    ] {
+        let result = rls_conn.execute(sql, params![]).await;
+        assert!(matches!(result, Err(Error::PermissionDenied(_))), "{sql}");
+    }
+    rls_conn.set_role("keeper");
+    rls_conn.execute("CREATE INDEX notes_body ON notes (body)", params![]).await?;
+    rls_conn.set_role("writer");
+
+    rls_conn.set_admin(true);
+    rls_conn.execute("GRANT ALL PRIVILEGES ON drafts, notes TO PUBLIC", params![]).await?;
+    rls_conn.execute("INSERT INTO notes (id, body) SELECT id, body FROM drafts", params![]).await?;
+
+    for sql in [
+        "GRANT SELECT ON notes TO nobody",
+        "GRANT SELECT, TRUNCATE ON notes TO writer",
+        "GRANT SELECT ON notes",
+        "REVOKE SELECT ON notes TO writer",
+        "DROP ROLE writer",
+    ] {
+        let result = rls_conn.execute(sql, params![]).await;
+        assert!(matches!(result, Err(Error::Policy(_))), "{sql}: {result:?}");
+    }
+
+    rls_conn.set_admin(false);
+    let result = rls_conn.execute("GRANT SELECT ON notes TO writer", params![]).await;
+    assert!(matches!(result, Err(Error::PermissionDenied(_))));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_column_privileges() -> Result<()> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connect()?;
+
+    conn.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, email TEXT, active INTEGER)", params![]).await?;
+    conn.execute("CREATE TABLE orders (id INTEGER PRIMARY KEY, user_id INTEGER)", params![]).await?;
+    conn.execute("CREATE TABLE guesses (email TEXT)", params![]).await?;
+    conn.execute("CREATE TABLE salaries (employee_id INTEGER PRIMARY KEY, name TEXT)", params![]).await?;
+    conn.execute("INSERT INTO salaries (employee_id, name) VALUES (4242, 'ann')", params![]).await?;
+    conn.execute(
+        "INSERT INTO users (id, name, email, active) VALUES (1, 'ann', 'ann@example.com', 1), (2, 'bob', 'bob@example.com', 0)",
+        params![],
+    ).await?;
+    conn.execute("INSERT INTO orders (id, user_id) VALUES (10, 1)", params![]).await?;
+
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+    rls_conn.set_admin(true);
+    for sql in [
+        "CREATE ROLE support",
+        "GRANT SELECT (id, name), UPDATE ON users TO support",
+        "GRANT SELECT (name) ON salaries TO support",
+        "CREATE POLICY active ON users USING (active = 1)",
+    ] {
+        rls_conn.execute(sql, params![]).await?;
+    }
+    rls_conn.set_admin(false);
+    rls_conn.set_role("support");
+
+    // * only stands for the readable columns, and policies still apply
+    let mut rows = rls_conn.query("SELECT * FROM users", params![]).await?;
+    let columns: Vec<_> = (0..rows.column_count()).map(|i| rows.column_name(i).map(str::to_string)).collect();
+    assert_eq!(columns, vec![Some("id".to_string()), Some("name".to_string())]);
+    let row = rows.next()?.expect("ann is visible");
+    assert_eq!(row.get::<String>(1)?, "ann");
+    assert!(rows.next()?.is_none());
+
+    let rows = rls_conn.query("SELECT u.*, o.* FROM users AS u JOIN orders AS o ON o.user_id = u.id", params![]).await?;
+    assert_eq!(rows.column_count(), 4);
+    let rows = rls_conn.query("SELECT * FROM orders WHERE user_id IN (SELECT id FROM users)", params![]).await?;
+    assert_eq!(rows.column_count(), 2);
+    rls_conn.query("SELECT users.name FROM users JOIN orders USING (id)", params![]).await?;
+
+    for sql in [
+        "SELECT email FROM users",
+        "SELECT u.email FROM users AS u",
+        "SELECT id FROM users WHERE email LIKE 'a%'",
+        "SELECT id FROM users ORDER BY email",
+        "SELECT upper(email) AS shouting FROM users",
+        "SELECT id FROM orders WHERE user_id IN (SELECT id FROM users WHERE email = 'ann@example.com')",
+        "SELECT o.id FROM orders AS o JOIN users AS u ON u.email = 'ann@example.com'",
+        "SELECT (SELECT max(active) FROM users) FROM orders",
+        // Joins on the columns both tables have read those columns too
+        "SELECT users.id FROM users JOIN guesses USING (email)",
+        "SELECT id FROM users NATURAL JOIN guesses",
+        "UPDATE users SET name = email",
+        // The rowid is the INTEGER PRIMARY KEY under other names
+        "SELECT rowid, name FROM salaries",
+        "SELECT s.OID FROM salaries AS s",
+        "SELECT name FROM salaries ORDER BY _rowid_",
+    ] {
+        let result = rls_conn.query(sql, params![]).await;
+        assert!(matches!(result, Err(Error::PermissionDenied(_))), "{sql}");
+    }
+    rls_conn.execute("UPDATE users SET name = 'ann' WHERE id = 1", params![]).await?;
+    let mut rows = rls_conn.query("SELECT rowid FROM users", params![]).await?;
+    assert_eq!(rows.next()?.expect("ann is visible").get::<i64>(0)?, 1);
+    drop(rows);
+
+    rls_conn.set_admin(true);
+    rls_conn.execute("GRANT SELECT ON users TO support", params![]).await?;
+    rls_conn.query("SELECT email FROM users", params![]).await?;
+
+    // Revoking SELECT on the table revokes it on its columns too
+    rls_conn.execute("REVOKE SELECT ON users FROM support", params![]).await?;
+    let result = rls_conn.query("SELECT id FROM users", params![]).await;
+    assert!(matches!(result, Err(Error::PermissionDenied(_))));
+
+    Ok(())
+}
@@ -0,0 +1,548 @@
+use libsql_rls::{Error, Result, RlsConnection};
+use libsql::{Database, params};
+
+#[tokio::test]
+async fn test_insert_with_check() -> Result<()> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connect()?;
+
+    conn.execute(
+        "CREATE TABLE documents (
+            id INTEGER PRIMARY KEY,
+            title TEXT NOT NULL,
+            tenant_id INTEGER NOT NULL DEFAULT 100
+        )",
+        params![],
+    ).await?;
+
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+
+    rls_conn.set_admin(true);
+    rls_conn.set_context("tenant_id", 100);
+    rls_conn.execute(
+        "CREATE POLICY tenant_insert ON documents FOR INSERT WITH CHECK (tenant_id = :tenant_id)",
+        params![],
+    ).await?;
+    rls_conn.execute(
+        "CREATE POLICY tenant_select ON documents FOR SELECT USING (tenant_id = :tenant_id)",
+        params![],
+    ).await?;
+
+    rls_conn.execute(
+        "INSERT INTO documents (id, title, tenant_id) VALUES (1, 'mine', 100)",
+        params![],
+    ).await?;
+    // Omitted columns are checked with their default value
+    rls_conn.execute("INSERT INTO documents (id, title) VALUES (?, ?)", params![2, "default"]).await?;
+
+    // A single offending row rejects the whole statement
+    let result = rls_conn.execute(
+        "INSERT INTO documents (id, title, tenant_id) VALUES (3, 'mine', 100), (4, 'theirs', ?)",
+        params![200],
+    ).await;
+    match result {
+        Err(Error::PolicyViolation { policy, table }) => {
+            assert_eq!(policy.as_deref(), Some("tenant_insert"));
+            assert_eq!(table, "documents");
+        }
+        other => panic!("expected a policy violation, got {:?}", other),
+    }
+
+    // ALL policies without WITH CHECK check their USING expression
+    rls_conn.execute(
+        "CREATE POLICY only_drafts ON documents AS RESTRICTIVE USING (title <> 'final')",
+        params![],
+    ).await?;
+    let result = rls_conn.execute(
+        "INSERT INTO documents (id, title, tenant_id) VALUES (5, 'final', 100)",
+        params![],
+    ).await;
+    assert!(matches!(
+        result,
+        Err(Error::PolicyViolation { policy: Some(ref policy), .. }) if policy == "only_drafts"
+    ));
+
+    let mut rows = rls_conn.query("SELECT COUNT(*) FROM documents", params![]).await?;
+    assert_eq!(rows.next()?.unwrap().get::<i64>(0)?, 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_update_using_and_with_check() -> Result<()> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connect()?;
+
+    conn.execute(
+        "CREATE TABLE documents (id INTEGER PRIMARY KEY, title TEXT NOT NULL, tenant_id INTEGER NOT NULL)",
+        params![],
+    ).await?;
+    conn.execute(
+        "CREATE TABLE renames (document_id INTEGER NOT NULL, title TEXT NOT NULL, tenant_id INTEGER NOT NULL)",
+        params![],
+    ).await?;
+    conn.execute(
+        "INSERT INTO documents (id, title, tenant_id) VALUES (1, 'a', 100), (2, 'b', 200), (3, 'c', 100)",
+        params![],
+    ).await?;
+    conn.execute(
+        "INSERT INTO renames (document_id, title, tenant_id) VALUES (1, 'renamed', 100), (2, 'renamed', 200), (3, 'hidden', 200)",
+        params![],
+    ).await?;
+
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+
+    rls_conn.set_admin(true);
+    rls_conn.set_context("tenant_id", 100);
+    rls_conn.execute("CREATE POLICY tenant ON documents USING (tenant_id = :tenant_id)", params![]).await?;
+    rls_conn.execute("CREATE POLICY tenant ON renames USING (tenant_id = :tenant_id)", params![]).await?;
+
+    // USING limits the targeted rows
+    assert_eq!(rls_conn.execute("UPDATE documents SET title = title || '!'", params![]).await?, 2);
+
+    // UPDATE ... FROM only joins the rows the caller can see
+    let updated = rls_conn.execute(
+        "UPDATE documents SET title = r.title FROM renames AS r WHERE r.document_id = documents.id",
+        params![],
+    ).await?;
+    assert_eq!(updated, 1);
+
+    // The updated row must still pass the policy
+    let result = rls_conn.execute("UPDATE documents SET tenant_id = ? WHERE id = 3", params![200]).await;
+    assert!(matches!(
+        result,
+        Err(Error::PolicyViolation { policy: Some(ref policy), .. }) if policy == "tenant"
+    ));
+
+    let mut rows = rls_conn.query("SELECT id, title FROM documents ORDER BY id", params![]).await?;
+    let mut documents = Vec::new();
+    while let Some(row) = rows.next()? {
+        documents.push((row.get::<i64>(0)?, row.get::<String>(1)?));
+    }
+    assert_eq!(documents, vec![(1, "renamed".to_string()), (3, "c!".to_string())]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_delete_using() -> Result<()> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connect()?;
+
+    conn.execute(
+        "CREATE TABLE users (id INTEGER PRIMARY KEY, tenant_id INTEGER NOT NULL)",
+        params![],
+    ).await?;
+    conn.execute(
+        "CREATE TABLE banned (user_id INTEGER NOT NULL, tenant_id INTEGER NOT NULL)",
+        params![],
+    ).await?;
+    conn.execute(
+        "INSERT INTO users (id, tenant_id) VALUES (1, 100), (2, 100), (3, 100), (4, 200)",
+        params![],
+    ).await?;
+    // The ban of user 2 belongs to another tenant
+    conn.execute("INSERT INTO banned (user_id, tenant_id) VALUES (1, 100), (2, 200)", params![]).await?;
+
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+
+    rls_conn.set_admin(true);
+    rls_conn.execute("CREATE POLICY tenant ON users USING (tenant_id = 100)", params![]).await?;
+    rls_conn.execute("CREATE POLICY tenant ON banned USING (tenant_id = 100)", params![]).await?;
+
+    // The subquery only sees the caller's bans
+    let deleted = rls_conn.execute(
+        "DELETE FROM users WHERE id IN (SELECT user_id FROM banned)",
+        params![],
+    ).await?;
+    assert_eq!(deleted, 1);
+
+    // Other tenants' rows are out of reach
+    assert_eq!(rls_conn.execute("DELETE FROM users", params![]).await?, 2);
+
+    // Without a DELETE policy nothing can be deleted
+    rls_conn.execute("DROP POLICY tenant ON users", params![]).await?;
+    rls_conn.execute("ALTER TABLE users ENABLE ROW LEVEL SECURITY", params![]).await?;
+    rls_conn.execute(
+        "CREATE POLICY tenant_read ON users FOR SELECT USING (true)",
+        params![],
+    ).await?;
+    assert_eq!(rls_conn.execute("DELETE FROM users WHERE id = ?", params![4]).await?, 0);
+
+    let mut rows = rls_conn.query("SELECT id FROM users", params![]).await?;
+    assert_eq!(rows.next()?.unwrap().get::<i64>(0)?, 4);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_upsert_checks_update_policies() -> Result<()> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connect()?;
+
+    conn.execute(
+        "CREATE TABLE documents (
+            id INTEGER PRIMARY KEY,
+            slug TEXT NOT NULL UNIQUE,
+            title TEXT NOT NULL,
+            tenant_id INTEGER NOT NULL
+        )",
+        params![],
+    ).await?;
+    conn.execute(
+        "INSERT INTO documents (id, slug, title, tenant_id) VALUES (1, 'a', 'a', 100), (2, 'b', 'b', 200)",
+        params![],
+    ).await?;
+
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+
+    rls_conn.set_admin(true);
+    rls_conn.execute("CREATE POLICY tenant ON documents USING (tenant_id = 100)", params![]).await?;
+
+    // Both new and conflicting rows of the caller's tenant go through
+    rls_conn.execute(
+        "INSERT INTO documents (id, slug, title, tenant_id) VALUES (1, 'a', 'edited', 100), (3, 'c', 'c', 100)
+         ON CONFLICT (id) DO UPDATE SET title = excluded.title || title",
+        params![],
+    ).await?;
+
+    // The existing row must pass USING, even though the proposed row passes
+    // the INSERT check
+    let result = rls_conn.execute(
+        "INSERT INTO documents (id, slug, title, tenant_id) VALUES (2, 'b', 'stolen', 100)
+         ON CONFLICT (id) DO UPDATE SET title = excluded.title",
+        params![],
+    ).await;
+    assert!(matches!(
+        result,
+        Err(Error::PolicyViolation { policy: Some(ref policy), .. }) if policy == "tenant"
+    ));
+
+    // Without a conflict target, rows conflict on any unique key
+    let result = rls_conn.execute(
+        "INSERT INTO documents (id, slug, title, tenant_id) VALUES (4, 'b', 'stolen', 100)
+         ON CONFLICT DO UPDATE SET title = excluded.title",
+        params![],
+    ).await;
+    assert!(matches!(result, Err(Error::PolicyViolation { .. })));
+
+    // The updated row must pass WITH CHECK
+    let result = rls_conn.execute(
+        "INSERT INTO documents (id, slug, title, tenant_id) VALUES (1, 'a', 'given away', 100)
+         ON CONFLICT (id) DO UPDATE SET tenant_id = 200",
+        params![],
+    ).await;
+    assert!(matches!(result, Err(Error::PolicyViolation { .. })));
+
+    let mut rows = rls_conn.query("SELECT id, title FROM documents ORDER BY id", params![]).await?;
+    let mut documents = Vec::new();
+    while let Some(row) = rows.next()? {
+        documents.push((row.get::<i64>(0)?, row.get::<String>(1)?));
+    }
+    assert_eq!(documents, vec![(1, "editeda".to_string()), (3, "c".to_string())]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_returning_only_visible_rows() -> Result<()> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connect()?;
+
+    conn.execute(
+        "CREATE TABLE documents (
+            id INTEGER PRIMARY KEY,
+            title TEXT NOT NULL,
+            tenant_id INTEGER NOT NULL,
+            published INTEGER NOT NULL
+        )",
+        params![],
+    ).await?;
+    conn.execute(
+        "INSERT INTO documents (id, title, tenant_id, published) VALUES (1, 'a', 100, 1), (2, 'b', 100, 0), (3, 'c', 200, 1)",
+        params![],
+    ).await?;
+
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+
+    rls_conn.set_admin(true);
+    rls_conn.execute("CREATE POLICY writable ON documents FOR INSERT WITH CHECK (tenant_id = 100)", params![]).await?;
+    rls_conn.execute("CREATE POLICY editable ON documents FOR UPDATE USING (tenant_id = 100)", params![]).await?;
+    rls_conn.execute("CREATE POLICY deletable ON documents FOR DELETE USING (tenant_id = 100)", params![]).await?;
+    rls_conn.execute(
+        "CREATE POLICY readable ON documents FOR SELECT USING (tenant_id = 100 AND published = 1)",
+        params![],
+    ).await?;
+
+    async fn returned_ids(rls_conn: &RlsConnection, sql: &str) -> Result<Vec<i64>> {
+        let mut rows = rls_conn.query(sql, params![]).await?;
+        let mut ids = Vec::new();
+        while let Some(row) = rows.next()? {
+            ids.push(row.get::<i64>(0)?);
+        }
+        ids.sort();
+        Ok(ids)
+    }
+
+    // Rows the caller can update but not read are skipped
+    assert_eq!(returned_ids(&rls_conn, "UPDATE documents SET title = 'edited' RETURNING id").await?, vec![1]);
+
+    // Inserted rows must be readable to be returned
+    assert_eq!(
+        returned_ids(&rls_conn, "INSERT INTO documents VALUES (4, 'd', 100, 1) RETURNING id").await?,
+        vec![4]
+    );
+    let result = rls_conn.query("INSERT INTO documents VALUES (5, 'e', 100, 0) RETURNING id", params![]).await;
+    assert!(matches!(
+        result,
+        Err(Error::PolicyViolation { policy: Some(ref policy), .. }) if policy == "readable"
+    ));
+
+    // So must updated rows
+    let result = rls_conn.query("UPDATE documents SET published = 0 WHERE id = 1 RETURNING id", params![]).await;
+    assert!(matches!(result, Err(Error::PolicyViolation { .. })));
+
+    assert_eq!(returned_ids(&rls_conn, "DELETE FROM documents RETURNING id").await?, vec![1, 4]);
+
+    // Without RETURNING, the hidden row is still in reach of the DELETE policy
+    assert_eq!(rls_conn.execute("DELETE FROM documents", params![]).await?, 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_insert_select_and_replace() -> Result<()> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connect()?;
+
+    conn.execute(
+        "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL, tenant_id INTEGER NOT NULL)",
+        params![],
+    ).await?;
+    conn.execute(
+        "CREATE TABLE archive (id INTEGER PRIMARY KEY, name TEXT NOT NULL, tenant_id INTEGER NOT NULL)",
+        params![],
+    ).await?;
+    conn.execute(
+        "INSERT INTO users (id, name, tenant_id) VALUES (1, 'alice', 100), (2, 'bob', 200), (3, 'carol', 100)",
+        params![],
+    ).await?;
+
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+
+    rls_conn.set_admin(true);
+    rls_conn.execute("CREATE POLICY tenant ON users USING (tenant_id = 100)", params![]).await?;
+    rls_conn.execute("CREATE POLICY tenant ON archive USING (tenant_id = 100)", params![]).await?;
+
+    // Only visible rows are copied, and they are the ones checked
+    assert_eq!(rls_conn.execute("INSERT INTO archive SELECT * FROM users", params![]).await?, 2);
+
+    // Copied rows must pass the target's checks
+    let result = rls_conn.execute(
+        "INSERT INTO archive (id, name, tenant_id) SELECT id + 10, name, 200 FROM users",
+        params![],
+    ).await;
+    assert!(matches!(result, Err(Error::PolicyViolation { ref table, .. }) if table == "archive"));
+
+    // REPLACE deletes the conflicting row, which must pass the DELETE policies
+    rls_conn.execute("REPLACE INTO users (id, name, tenant_id) VALUES (1, 'alicia', 100)", params![]).await?;
+    let result = rls_conn.execute(
+        "INSERT OR REPLACE INTO users (id, name, tenant_id) VALUES (2, 'mallory', 100)",
+        params![],
+    ).await;
+    assert!(matches!(
+        result,
+        Err(Error::PolicyViolation { policy: Some(ref policy), .. }) if policy == "tenant"
+    ));
+
+    let mut rows = rls_conn.query(
+        "SELECT u.id, u.name FROM users AS u JOIN archive AS a ON a.id = u.id ORDER BY u.id",
+        params![],
+    ).await?;
+    let mut users = Vec::new();
+    while let Some(row) = rows.next()? {
+        users.push((row.get::<i64>(0)?, row.get::<String>(1)?));
+    }
+    assert_eq!(users, vec![(1, "alicia".to_string()), (3, "carol".to_string())]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_insert_checks_the_inserted_rows() -> Result<()> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connect()?;
+
+    conn.execute(
+        "CREATE TABLE documents (id INTEGER PRIMARY KEY, title TEXT NOT NULL, tenant_id INTEGER NOT NULL)",
+        params![],
+    ).await?;
+
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+    rls_conn.set_admin(true);
+    rls_conn.execute("CREATE POLICY tenant ON documents USING (tenant_id = 100)", params![]).await?;
+
+    // Values computed anew on each evaluation can't differ between the
+    // check and the row actually inserted
+    let mut violations = 0;
+    for id in 1..=40 {
+        let sql = "INSERT INTO documents (id, title, tenant_id)
+                   VALUES (?, 'random', CASE WHEN random() % 2 = 0 THEN 100 ELSE 200 END) RETURNING tenant_id";
+        let result = if id % 2 == 0 {
+            rls_conn.execute(&sql.replace(" RETURNING tenant_id", ""), params![id]).await
+        } else {
+            match rls_conn.query(sql, params![id]).await {
+                Ok(mut rows) => {
+                    assert_eq!(rows.next()?.expect("the row is returned").get::<i64>(0)?, 100);
+                    Ok(1)
+                }
+                Err(e) => Err(e),
+            }
+        };
+        match result {
+            Ok(_) => {}
+            Err(Error::PolicyViolation { .. }) => violations += 1,
+            Err(e) => return Err(e),
+        }
+    }
+    assert!(violations > 0);
+
+    // A violation only undoes its own statement
+    rls_conn.execute("BEGIN", params![]).await?;
+    rls_conn.execute("INSERT INTO documents (id, title, tenant_id) VALUES (100, 'kept', 100)", params![]).await?;
+    let result = rls_conn.execute("INSERT INTO documents (id, title, tenant_id) VALUES (101, 'lost', 200)", params![]).await;
+    assert!(matches!(result, Err(Error::PolicyViolation { .. })));
+    rls_conn.execute("COMMIT", params![]).await?;
+
+    rls_conn.execute("ALTER TABLE documents DISABLE ROW LEVEL SECURITY", params![]).await?;
+    let mut rows = rls_conn.query("SELECT count(*), count(*) FILTER (WHERE tenant_id <> 100) FROM documents", params![]).await?;
+    let row = rows.next()?.expect("a count");
+    assert_eq!((row.get::<i64>(0)?, row.get::<i64>(1)?), (41 - violations, 0));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_update_checks_the_updated_rows() -> Result<()> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connect()?;
+
+    conn.execute(
+        "CREATE TABLE documents (id INTEGER PRIMARY KEY, title TEXT NOT NULL, tenant_id INTEGER NOT NULL)",
+        params![],
+    ).await?;
+    conn.execute("INSERT INTO documents (id, title, tenant_id) VALUES (1, 'a', 100)", params![]).await?;
+
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+    rls_conn.set_admin(true);
+    rls_conn.execute("CREATE POLICY tenant ON documents USING (tenant_id = 100)", params![]).await?;
+
+    let mut violations = 0;
+    for attempt in 0..40 {
+        let sql = "UPDATE documents SET tenant_id = CASE WHEN random() % 2 = 0 THEN 100 ELSE 300 END
+                   WHERE id = 1 RETURNING tenant_id";
+        let result = if attempt % 2 == 0 {
+            rls_conn.execute(&sql.replace(" RETURNING tenant_id", ""), params![]).await
+        } else {
+            match rls_conn.query(sql, params![]).await {
+                Ok(mut rows) => {
+                    assert_eq!(rows.next()?.expect("the row is returned").get::<i64>(0)?, 100);
+                    Ok(1)
+                }
+                Err(e) => Err(e),
+            }
+        };
+        match result {
+            Ok(updated) => assert_eq!(updated, 1),
+            Err(Error::PolicyViolation { .. }) => violations += 1,
+            Err(e) => return Err(e),
+        }
+    }
+    assert!(violations > 0);
+
+    let mut rows = rls_conn.query("SELECT id FROM documents", params![]).await?;
+    assert_eq!(rows.next()?.expect("the row stays visible").get::<i64>(0)?, 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_without_rowid_tables_check_the_written_rows() -> Result<()> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connect()?;
+
+    conn.execute("CREATE TABLE kv (k TEXT PRIMARY KEY, tenant_id INTEGER NOT NULL) WITHOUT ROWID", params![]).await?;
+    conn.execute(
+        "CREATE TABLE pairs (a TEXT, b BLOB, tenant_id INTEGER NOT NULL, PRIMARY KEY (a, b)) WITHOUT ROWID",
+        params![],
+    ).await?;
+
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+    rls_conn.set_admin(true);
+    for sql in [
+        "CREATE POLICY tenant ON kv USING (tenant_id = 100)",
+        "CREATE POLICY tenant ON pairs USING (tenant_id = 100)",
+    ] {
+        rls_conn.execute(sql, params![]).await?;
+    }
+    rls_conn.set_admin(false);
+
+    rls_conn.execute("INSERT INTO kv VALUES ('a', 100), ('b', 100)", params![]).await?;
+    rls_conn.execute("INSERT INTO pairs VALUES ('a,', x'01', 100), ('a', x'2c01', 100)", params![]).await?;
+    let mut rows = rls_conn.query("INSERT INTO pairs VALUES ('b', x'02', 100) RETURNING b", params![]).await?;
+    assert_eq!(rows.next()?.expect("the row is returned").get::<Vec<u8>>(0)?, vec![0x02]);
+    drop(rows);
+    rls_conn.execute("UPDATE kv SET tenant_id = 100", params![]).await?;
+    let mut rows = rls_conn.query("UPDATE pairs SET tenant_id = 100 WHERE a = 'a' RETURNING b", params![]).await?;
+    assert_eq!(rows.next()?.expect("the row is returned").get::<Vec<u8>>(0)?, vec![0x2c, 0x01]);
+    drop(rows);
+
+    for sql in [
+        "INSERT INTO kv VALUES ('c', 200)",
+        "INSERT INTO pairs VALUES ('c', x'03', 200)",
+        "UPDATE kv SET tenant_id = 200 WHERE k = 'b'",
+        "UPDATE pairs SET tenant_id = 200 WHERE a = 'a,'",
+    ] {
+        let result = rls_conn.execute(sql, params![]).await;
+        assert!(matches!(result, Err(Error::PolicyViolation { .. })), "{sql}: {result:?}");
+    }
+
+    let mut rows = rls_conn.query("SELECT count(*) FROM kv JOIN pairs USING (tenant_id)", params![]).await?;
+    assert_eq!(rows.next()?.expect("one row").get::<i64>(0)?, 6);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_returning_many_checked_rows() -> Result<()> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connect()?;
+
+    conn.execute("CREATE TABLE big (id INTEGER PRIMARY KEY, tenant_id INTEGER NOT NULL)", params![]).await?;
+    conn.execute(
+        "WITH RECURSIVE ids(id) AS (SELECT 1 UNION ALL SELECT id + 1 FROM ids WHERE id < 40000)
+         INSERT INTO big (id, tenant_id) SELECT id, 100 FROM ids",
+        params![],
+    ).await?;
+
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+    rls_conn.set_admin(true);
+    rls_conn.execute("CREATE POLICY tenant ON big USING (tenant_id = 100)", params![]).await?;
+    rls_conn.set_admin(false);
+
+    // More values than SQLite binds to one statement
+    let mut rows = rls_conn.query("UPDATE big SET tenant_id = 100 RETURNING id, 'x'", params![]).await?;
+    let mut ids = Vec::new();
+    while let Some(row) = rows.next()? {
+        ids.push(row.get::<i64>(0)?);
+    }
+    ids.sort();
+    assert_eq!(ids, (1..=40000).collect::<Vec<_>>());
+    drop(rows);
+
+    let mut rows = rls_conn.query("UPDATE big SET tenant_id = 100 WHERE id = 0 RETURNING id", params![]).await?;
+    assert_eq!(rows.column_count(), 1);
+    assert!(rows.next()?.is_none());
+
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use libsql_rls::{Result, RlsConnection};
+use libsql_rls::{Result, RewriteStrategy, RlsConnection};
 use libsql::{Database, params};
 
 #[tokio::test]
@@ -36,7 +36,8 @@ async fn test_rls_for_select() -> Result<()> {
     assert_eq!(count, 4, "Should have 4 users in total");
     
     // Now create the RLS connection
-    let rls_conn = RlsConnection::new_initialized(conn).await?;
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+    rls_conn.set_admin(true);
     
     // Create a policy: users can only see data from tenant_id = 100
     rls_conn.execute(
@@ -86,4 +87,503 @@ async fn test_rls_for_select() -> Result<()> {
     assert_eq!(matched_users, 1, "Only 'alice' should match with RLS applied");
     
     Ok(())
-} 
\ No newline at end of file
+} 
+#[tokio::test]
+async fn test_rls_compound_policy_expression() -> Result<()> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connect()?;
+
+    conn.execute(
+        "CREATE TABLE documents (
+            id INTEGER PRIMARY KEY,
+            tenant_id INTEGER NOT NULL,
+            is_public INTEGER NOT NULL,
+            owner_id INTEGER,
+            archived_at TEXT
+        )",
+        params![],
+    ).await?;
+
+    conn.execute(
+        "INSERT INTO documents (id, tenant_id, is_public, owner_id, archived_at) VALUES
+        (1, 100, 1, 1, NULL),
+        (2, 100, 0, 7, NULL),
+        (3, 100, 0, 8, NULL),
+        (4, 200, 1, 7, NULL),
+        (5, 100, 0, 7, '2024-01-01')",
+        params![],
+    ).await?;
+
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+
+    rls_conn.set_admin(true);
+
+    rls_conn.execute(
+        "CREATE POLICY tenant_docs ON documents
+         USING (tenant_id = 100 AND (is_public OR owner_id = 7) AND archived_at IS NULL AND id IN (1, 2, 3, 4, 5))",
+        params![],
+    ).await?;
+
+    // The user's OR must not escape the policy conjunction
+    let mut rows = rls_conn.query(
+        "SELECT id FROM documents WHERE id >= 3 OR is_public ORDER BY id",
+        params![],
+    ).await?;
+
+    let mut ids = Vec::new();
+    while let Some(row) = rows.next()? {
+        ids.push(row.get::<i64>(0)?);
+    }
+
+    assert_eq!(ids, vec![1]);
+
+    let mut rows = rls_conn.query("SELECT id FROM documents ORDER BY id", params![]).await?;
+    let mut ids = Vec::new();
+    while let Some(row) = rows.next()? {
+        ids.push(row.get::<i64>(0)?);
+    }
+
+    assert_eq!(ids, vec![1, 2]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_permissive_and_restrictive_policies() -> Result<()> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connect()?;
+
+    conn.execute(
+        "CREATE TABLE users (
+            id INTEGER PRIMARY KEY,
+            username TEXT NOT NULL,
+            tenant_id INTEGER NOT NULL,
+            active INTEGER NOT NULL
+        )",
+        params![],
+    ).await?;
+
+    conn.execute(
+        "INSERT INTO users (id, username, tenant_id, active) VALUES
+        (1, 'alice', 100, 1),
+        (2, 'bob', 100, 0),
+        (3, 'charlie', 200, 1),
+        (4, 'dave', 300, 1)",
+        params![],
+    ).await?;
+
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+
+    rls_conn.set_admin(true);
+
+    // Only restrictive policies: nothing is visible
+    rls_conn.execute(
+        "CREATE POLICY only_active ON users AS RESTRICTIVE USING (active = 1)",
+        params![],
+    ).await?;
+    assert_eq!(visible_user_ids(&rls_conn).await?, Vec::<i64>::new());
+
+    // A permissive policy grants access, still limited by the restrictive one
+    rls_conn.execute(
+        "CREATE POLICY tenant_100 ON users AS PERMISSIVE USING (tenant_id = 100)",
+        params![],
+    ).await?;
+    assert_eq!(visible_user_ids(&rls_conn).await?, vec![1]);
+
+    // A second permissive policy widens access instead of narrowing it
+    rls_conn.execute(
+        "CREATE POLICY tenant_200 ON users USING (tenant_id = 200)",
+        params![],
+    ).await?;
+    assert_eq!(visible_user_ids(&rls_conn).await?, vec![1, 3]);
+
+    let mut rows = rls_conn.query(
+        "SELECT kind FROM _rls_policies WHERE name = ?",
+        params!["only_active"],
+    ).await?;
+    assert_eq!(rows.next()?.unwrap().get::<String>(0)?, "RESTRICTIVE");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_enable_disable_row_level_security() -> Result<()> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connect()?;
+
+    conn.execute(
+        "CREATE TABLE users (
+            id INTEGER PRIMARY KEY,
+            username TEXT NOT NULL,
+            tenant_id INTEGER NOT NULL
+        )",
+        params![],
+    ).await?;
+
+    conn.execute(
+        "INSERT INTO users (id, username, tenant_id) VALUES
+        (1, 'alice', 100),
+        (2, 'bob', 200)",
+        params![],
+    ).await?;
+
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+
+    rls_conn.set_admin(true);
+    assert_eq!(visible_user_ids(&rls_conn).await?, vec![1, 2]);
+
+    // Enabled without any policy: fail closed
+    rls_conn.execute("ALTER TABLE users ENABLE ROW LEVEL SECURITY", params![]).await?;
+    assert_eq!(visible_user_ids(&rls_conn).await?, Vec::<i64>::new());
+
+    rls_conn.execute(
+        "CREATE POLICY tenant_100 ON users USING (tenant_id = 100)",
+        params![],
+    ).await?;
+    assert_eq!(visible_user_ids(&rls_conn).await?, vec![1]);
+
+    // FORCE is recorded without changing whether policies apply
+    rls_conn.execute("alter table users force row level security;", params![]).await?;
+    let mut rows = rls_conn.query(
+        "SELECT enabled, forced FROM _rls_tables WHERE table_name = ?",
+        params!["users"],
+    ).await?;
+    let row = rows.next()?.unwrap();
+    assert_eq!((row.get::<i64>(0)?, row.get::<i64>(1)?), (1, 1));
+    assert_eq!(visible_user_ids(&rls_conn).await?, vec![1]);
+
+    // Disabled tables ignore their policies
+    rls_conn.execute("ALTER TABLE users DISABLE ROW LEVEL SECURITY", params![]).await?;
+    assert_eq!(visible_user_ids(&rls_conn).await?, vec![1, 2]);
+
+    // Other ALTER TABLE statements still reach libSQL
+    rls_conn.execute("ALTER TABLE users ADD COLUMN email TEXT", params![]).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rls_across_joins() -> Result<()> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connect()?;
+
+    conn.execute(
+        "CREATE TABLE users (id INTEGER PRIMARY KEY, username TEXT NOT NULL, tenant_id INTEGER NOT NULL)",
+        params![],
+    ).await?;
+    conn.execute(
+        "CREATE TABLE posts (id INTEGER PRIMARY KEY, user_id INTEGER NOT NULL, tenant_id INTEGER NOT NULL)",
+        params![],
+    ).await?;
+    conn.execute(
+        "INSERT INTO users (id, username, tenant_id) VALUES (1, 'alice', 100), (2, 'bob', 200)",
+        params![],
+    ).await?;
+    // Post 11 belongs to alice but was filed under the wrong tenant
+    conn.execute(
+        "INSERT INTO posts (id, user_id, tenant_id) VALUES (10, 1, 100), (11, 1, 200), (12, 2, 200)",
+        params![],
+    ).await?;
+
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+
+    rls_conn.set_admin(true);
+    rls_conn.execute("CREATE POLICY tenant_users ON users USING (tenant_id = 100)", params![]).await?;
+    rls_conn.execute("CREATE POLICY tenant_posts ON posts USING (posts.tenant_id = 100)", params![]).await?;
+
+    let queries = [
+        "SELECT p.id FROM users u JOIN posts p ON p.user_id = u.id ORDER BY p.id",
+        "SELECT posts.id FROM users JOIN posts ON posts.user_id = users.id ORDER BY posts.id",
+        "SELECT p.id FROM users AS u, posts AS p WHERE p.user_id = u.id ORDER BY p.id",
+    ];
+    for strategy in [RewriteStrategy::WhereClause, RewriteStrategy::DerivedTable] {
+        rls_conn.set_rewrite_strategy(strategy);
+        for sql in queries {
+            let mut rows = rls_conn.query(sql, params![]).await?;
+            let mut ids = Vec::new();
+            while let Some(row) = rows.next()? {
+                ids.push(row.get::<i64>(0)?);
+            }
+            assert_eq!(ids, vec![10], "{:?}: {}", strategy, sql);
+        }
+
+        // Both sides of a self-join are filtered
+        let mut rows = rls_conn.query(
+            "SELECT COUNT(*) FROM users a JOIN users b ON a.id <> b.id",
+            params![],
+        ).await?;
+        assert_eq!(rows.next()?.unwrap().get::<i64>(0)?, 0);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rls_preserves_outer_joins() -> Result<()> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connect()?;
+
+    conn.execute(
+        "CREATE TABLE users (id INTEGER PRIMARY KEY, tenant_id INTEGER NOT NULL)",
+        params![],
+    ).await?;
+    conn.execute(
+        "CREATE TABLE posts (id INTEGER PRIMARY KEY, user_id INTEGER NOT NULL, tenant_id INTEGER NOT NULL)",
+        params![],
+    ).await?;
+    conn.execute("INSERT INTO users (id, tenant_id) VALUES (1, 100), (2, 100)", params![]).await?;
+    // User 2 only has a post that is hidden by the posts policy
+    conn.execute(
+        "INSERT INTO posts (id, user_id, tenant_id) VALUES (10, 1, 100), (11, 2, 200)",
+        params![],
+    ).await?;
+
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+
+    rls_conn.set_admin(true);
+    rls_conn.execute("CREATE POLICY tenant_users ON users USING (tenant_id = 100)", params![]).await?;
+    rls_conn.execute("CREATE POLICY tenant_posts ON posts USING (tenant_id = 100)", params![]).await?;
+
+    let sql = "SELECT u.id, p.id FROM users u LEFT JOIN posts p ON p.user_id = u.id ORDER BY u.id";
+    let mut rows = rls_conn.query(sql, params![]).await?;
+    let mut pairs = Vec::new();
+    while let Some(row) = rows.next()? {
+        pairs.push((row.get::<i64>(0)?, row.get::<Option<i64>>(1)?));
+    }
+    assert_eq!(pairs, vec![(1, Some(10)), (2, None)]);
+
+    // Filtering in the WHERE clause drops users whose posts are all hidden
+    rls_conn.set_rewrite_strategy(RewriteStrategy::WhereClause);
+    let mut rows = rls_conn.query(sql, params![]).await?;
+    let mut pairs = Vec::new();
+    while let Some(row) = rows.next()? {
+        pairs.push((row.get::<i64>(0)?, row.get::<Option<i64>>(1)?));
+    }
+    assert_eq!(pairs, vec![(1, Some(10))]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rls_for_subqueries() -> Result<()> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connect()?;
+
+    conn.execute(
+        "CREATE TABLE users (id INTEGER PRIMARY KEY, tenant_id INTEGER NOT NULL)",
+        params![],
+    ).await?;
+    conn.execute(
+        "CREATE TABLE posts (id INTEGER PRIMARY KEY, user_id INTEGER NOT NULL, tenant_id INTEGER NOT NULL)",
+        params![],
+    ).await?;
+    conn.execute("INSERT INTO users (id, tenant_id) VALUES (1, 100), (2, 200), (3, 200)", params![]).await?;
+    conn.execute(
+        "INSERT INTO posts (id, user_id, tenant_id) VALUES (10, 1, 100), (11, 2, 200)",
+        params![],
+    ).await?;
+
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+
+    rls_conn.set_admin(true);
+    rls_conn.execute("CREATE POLICY tenant_users ON users USING (tenant_id = 100)", params![]).await?;
+    rls_conn.execute("CREATE POLICY tenant_posts ON posts USING (tenant_id = 100)", params![]).await?;
+
+    let cases: [(&str, i64); 6] = [
+        ("SELECT COUNT(*) FROM (SELECT * FROM users)", 1),
+        ("SELECT COUNT(*) FROM (SELECT * FROM (SELECT id FROM users) AS inner_users) AS u", 1),
+        ("SELECT (SELECT COUNT(*) FROM users)", 1),
+        ("SELECT COUNT(*) FROM posts WHERE user_id IN (SELECT id FROM users WHERE tenant_id = 200)", 0),
+        ("SELECT COUNT(*) FROM users u WHERE EXISTS (SELECT 1 FROM posts p WHERE p.user_id = u.id)", 1),
+        (
+            "SELECT COUNT(*) FROM users WHERE id NOT IN (SELECT user_id FROM posts WHERE id IN (SELECT id FROM posts))",
+            0,
+        ),
+    ];
+    for strategy in [RewriteStrategy::WhereClause, RewriteStrategy::DerivedTable] {
+        rls_conn.set_rewrite_strategy(strategy);
+        for (sql, expected) in cases {
+            let mut rows = rls_conn.query(sql, params![]).await?;
+            assert_eq!(rows.next()?.unwrap().get::<i64>(0)?, expected, "{:?}: {}", strategy, sql);
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rls_for_common_table_expressions() -> Result<()> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connect()?;
+
+    conn.execute(
+        "CREATE TABLE employees (id INTEGER PRIMARY KEY, manager_id INTEGER, org_id INTEGER NOT NULL)",
+        params![],
+    ).await?;
+    // Employee 3 reports to 2 but belongs to another org
+    conn.execute(
+        "INSERT INTO employees (id, manager_id, org_id) VALUES
+        (1, NULL, 1),
+        (2, 1, 1),
+        (3, 2, 2),
+        (4, 3, 1)",
+        params![],
+    ).await?;
+
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+
+    rls_conn.set_admin(true);
+    rls_conn.execute("CREATE POLICY same_org ON employees USING (org_id = 1)", params![]).await?;
+
+    let cases: [(&str, Vec<i64>); 3] = [
+        ("WITH e AS (SELECT id FROM employees) SELECT id FROM e ORDER BY id", vec![1, 2, 4]),
+        // The recursive term is filtered too, so the walk stops at employee 3
+        (
+            "WITH RECURSIVE reports(id) AS (
+                SELECT id FROM employees WHERE id = 1
+                UNION ALL
+                SELECT e.id FROM employees e JOIN reports r ON e.manager_id = r.id
+            )
+            SELECT id FROM reports ORDER BY id",
+            vec![1, 2],
+        ),
+        // A CTE shadowing a protected table is a local relation
+        (
+            "WITH employees(id) AS (SELECT 3 UNION ALL SELECT 5) SELECT id FROM employees ORDER BY id",
+            vec![3, 5],
+        ),
+    ];
+    for strategy in [RewriteStrategy::WhereClause, RewriteStrategy::DerivedTable] {
+        rls_conn.set_rewrite_strategy(strategy);
+        for (sql, expected) in &cases {
+            let mut rows = rls_conn.query(sql, params![]).await?;
+            let mut ids = Vec::new();
+            while let Some(row) = rows.next()? {
+                ids.push(row.get::<i64>(0)?);
+            }
+            assert_eq!(&ids, expected, "{:?}: {}", strategy, sql);
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rls_for_set_operations() -> Result<()> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connect()?;
+
+    conn.execute(
+        "CREATE TABLE users (id INTEGER PRIMARY KEY, tenant_id INTEGER NOT NULL)",
+        params![],
+    ).await?;
+    conn.execute(
+        "CREATE TABLE archived_users (id INTEGER PRIMARY KEY, tenant_id INTEGER NOT NULL)",
+        params![],
+    ).await?;
+    conn.execute("INSERT INTO users (id, tenant_id) VALUES (1, 100), (2, 200)", params![]).await?;
+    conn.execute("INSERT INTO archived_users (id, tenant_id) VALUES (2, 200), (3, 100), (4, 200)", params![]).await?;
+
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+
+    rls_conn.set_admin(true);
+    rls_conn.execute("CREATE POLICY tenant_users ON users USING (tenant_id = 100)", params![]).await?;
+    rls_conn.execute("CREATE POLICY tenant_archive ON archived_users USING (tenant_id = 100)", params![]).await?;
+
+    let cases: [(&str, Vec<i64>); 5] = [
+        ("SELECT id FROM users UNION SELECT id FROM users ORDER BY id", vec![1]),
+        ("SELECT id FROM users UNION ALL SELECT id FROM archived_users ORDER BY id", vec![1, 3]),
+        ("SELECT id FROM archived_users EXCEPT SELECT id FROM users ORDER BY id", vec![3]),
+        ("SELECT id FROM archived_users INTERSECT SELECT 2 ORDER BY id", vec![]),
+        (
+            "SELECT id FROM users UNION SELECT id FROM (SELECT id FROM archived_users UNION SELECT 5) ORDER BY id",
+            vec![1, 3, 5],
+        ),
+    ];
+    for strategy in [RewriteStrategy::WhereClause, RewriteStrategy::DerivedTable] {
+        rls_conn.set_rewrite_strategy(strategy);
+        for (sql, expected) in &cases {
+            let mut rows = rls_conn.query(sql, params![]).await?;
+            let mut ids = Vec::new();
+            while let Some(row) = rows.next()? {
+                ids.push(row.get::<i64>(0)?);
+            }
+            assert_eq!(&ids, expected, "{:?}: {}", strategy, sql);
+        }
+    }
+
+    Ok(())
+}
+
+async fn visible_user_ids(rls_conn: &RlsConnection) -> Result<Vec<i64>> {
+    let mut rows = rls_conn.query("SELECT id FROM users ORDER BY id", params![]).await?;
+    let mut ids = Vec::new();
+    while let Some(row) = rows.next()? {
+        ids.push(row.get::<i64>(0)?);
+    }
+    Ok(ids)
+}
+
+#[tokio::test]
+async fn test_table_names_ignore_case() -> Result<()> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connect()?;
+
+    conn.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, tenant_id INTEGER NOT NULL)", params![]).await?;
+    conn.execute("INSERT INTO users (id, tenant_id) VALUES (1, 100), (2, 200)", params![]).await?;
+
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+    rls_conn.set_admin(true);
+    rls_conn.execute("CREATE POLICY tenant ON Users USING (tenant_id = 100)", params![]).await?;
+    rls_conn.set_admin(false);
+
+    // SQLite resolves table names whatever their case, and so do policies
+    for sql in [
+        "SELECT id FROM USERS",
+        "SELECT id FROM \"USERS\"",
+        "SELECT u.id FROM users AS u JOIN USERS AS v ON v.id = u.id",
+    ] {
+        let mut rows = rls_conn.query(sql, params![]).await?;
+        assert_eq!(rows.next()?.expect("alice is visible").get::<i64>(0)?, 1, "{sql}");
+        assert!(rows.next()?.is_none(), "{sql}");
+    }
+    assert_eq!(rls_conn.execute("DELETE FROM USERS WHERE id = 2", params![]).await?, 0);
+
+    rls_conn.set_admin(true);
+    rls_conn.execute("ALTER TABLE USERS DISABLE ROW LEVEL SECURITY", params![]).await?;
+    let mut rows = rls_conn.query("SELECT count(*) FROM users", params![]).await?;
+    assert_eq!(rows.next()?.expect("a count").get::<i64>(0)?, 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rowid_of_protected_tables() -> Result<()> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connect()?;
+
+    conn.execute("CREATE TABLE docs (title TEXT NOT NULL, tenant_id INTEGER NOT NULL)", params![]).await?;
+    conn.execute("INSERT INTO docs (title, tenant_id) VALUES ('a', 100), ('b', 200), ('c', 100)", params![]).await?;
+
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+    rls_conn.set_admin(true);
+    rls_conn.execute("CREATE POLICY tenant ON docs USING (tenant_id = 100)", params![]).await?;
+    rls_conn.set_admin(false);
+
+    // The filtered derived table has no rowid, so these are filtered in
+    // their WHERE clause instead
+    for sql in [
+        "SELECT rowid FROM docs ORDER BY rowid",
+        "SELECT d.OID FROM docs AS d ORDER BY 1",
+        "SELECT _rowid_ FROM docs WHERE title <> 'x' ORDER BY _rowid_",
+    ] {
+        let mut rows = rls_conn.query(sql, params![]).await?;
+        let mut ids = Vec::new();
+        while let Some(row) = rows.next()? {
+            ids.push(row.get::<i64>(0)?);
+        }
+        assert_eq!(ids, vec![1, 3], "{sql}");
+    }
+
+    Ok(())
+}
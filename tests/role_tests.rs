@@ -0,0 +1,104 @@
+use libsql_rls::{Error, Result, RlsConnection};
+use libsql::{Database, params};
+
+async fn visible_ids(rls_conn: &RlsConnection) -> Result<Vec<i64>> {
+    let mut rows = rls_conn.query("SELECT id FROM documents ORDER BY id", params![]).await?;
+    let mut ids = Vec::new();
+    while let Some(row) = rows.next()? {
+        ids.push(row.get::<i64>(0)?);
+    }
+    Ok(ids)
+}
+
+#[tokio::test]
+async fn test_policies_apply_to_effective_roles() -> Result<()> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connect()?;
+
+    conn.execute(
+        "CREATE TABLE documents (id INTEGER PRIMARY KEY, owner_id INTEGER NOT NULL, published INTEGER NOT NULL)",
+        params![],
+    ).await?;
+    conn.execute(
+        "INSERT INTO documents (id, owner_id, published) VALUES (1, 1, 1), (2, 1, 0), (3, 2, 0), (4, 3, 0)",
+        params![],
+    ).await?;
+
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+    rls_conn.set_admin(true);
+    for sql in [
+        "CREATE ROLE authenticated",
+        "CREATE ROLE staff",
+        "GRANT authenticated TO staff",
+        "CREATE POLICY published ON documents FOR SELECT TO public USING (published = 1)",
+        "CREATE POLICY own ON documents FOR SELECT TO authenticated USING (owner_id = current_user_id())",
+    ] {
+        rls_conn.execute(sql, params![]).await?;
+    }
+
+    // Without a role only PUBLIC policies apply
+    assert_eq!(visible_ids(&rls_conn).await?, vec![1]);
+
+    rls_conn.set_role("authenticated");
+    rls_conn.set_user(1);
+    assert_eq!(visible_ids(&rls_conn).await?, vec![1, 2]);
+
+    // Staff inherit the policies of authenticated
+    rls_conn.set_role("staff");
+    rls_conn.set_user(2);
+    assert_eq!(visible_ids(&rls_conn).await?, vec![1, 3]);
+
+    rls_conn.execute("REVOKE authenticated FROM staff", params![]).await?;
+    assert_eq!(visible_ids(&rls_conn).await?, vec![1]);
+
+    rls_conn.execute(
+        "CREATE POLICY everything ON documents FOR SELECT TO staff USING (true)",
+        params![],
+    ).await?;
+    assert_eq!(visible_ids(&rls_conn).await?, vec![1, 2, 3, 4]);
+
+    // Retargeting the policy takes it away from staff
+    rls_conn.execute("ALTER POLICY everything ON documents TO authenticated", params![]).await?;
+    assert_eq!(visible_ids(&rls_conn).await?, vec![1]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_role_statement_errors() -> Result<()> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connect()?;
+
+    conn.execute("CREATE TABLE users (id INTEGER PRIMARY KEY)", params![]).await?;
+
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+    rls_conn.set_admin(true);
+    rls_conn.execute("CREATE ROLE staff", params![]).await?;
+    rls_conn.execute("CREATE ROLE admins", params![]).await?;
+    rls_conn.execute("GRANT staff TO admins", params![]).await?;
+    rls_conn.execute("CREATE POLICY staff_only ON users TO staff USING (true)", params![]).await?;
+
+    for sql in [
+        "CREATE ROLE staff",
+        "CREATE ROLE public",
+        "CREATE POLICY missing ON users TO nobody USING (true)",
+        "GRANT nobody TO staff",
+        // Circular membership
+        "GRANT admins TO staff",
+        "GRANT staff TO staff",
+        // Still used by a policy
+        "DROP ROLE staff",
+        "DROP ROLE nobody",
+    ] {
+        let result = rls_conn.execute(sql, params![]).await;
+        assert!(matches!(result, Err(Error::Policy(_))), "expected {sql} to fail, got {result:?}");
+    }
+
+    assert_eq!(rls_conn.execute("DROP ROLE IF EXISTS nobody", params![]).await?, 0);
+    assert_eq!(rls_conn.execute("DROP ROLE admins", params![]).await?, 1);
+
+    let mut rows = rls_conn.query("SELECT COUNT(*) FROM _rls_role_members", params![]).await?;
+    assert_eq!(rows.next()?.unwrap().get::<i64>(0)?, 0);
+
+    Ok(())
+}
@@ -0,0 +1,154 @@
+use libsql_rls::{Result, RlsConnection};
+use libsql::{Database, named_params, params};
+
+#[tokio::test]
+async fn test_session_context_functions() -> Result<()> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connect()?;
+
+    conn.execute(
+        "CREATE TABLE notes (
+            id INTEGER PRIMARY KEY,
+            owner_id INTEGER NOT NULL,
+            tenant_id TEXT NOT NULL,
+            audience TEXT NOT NULL
+        )",
+        params![],
+    ).await?;
+
+    conn.execute(
+        "INSERT INTO notes (id, owner_id, tenant_id, audience) VALUES
+        (1, 1, 'acme', 'staff'),
+        (2, 2, 'acme', 'staff'),
+        (3, 1, 'globex', 'staff'),
+        (4, 3, 'acme', 'admin')",
+        params![],
+    ).await?;
+
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+
+    rls_conn.set_admin(true);
+
+    rls_conn.execute(
+        "CREATE POLICY own_notes ON notes USING (owner_id = current_user_id())",
+        params![],
+    ).await?;
+    rls_conn.execute(
+        "CREATE POLICY tenant_notes ON notes AS RESTRICTIVE
+         USING (tenant_id = current_setting('app.tenant_id'))",
+        params![],
+    ).await?;
+    rls_conn.execute(
+        "CREATE POLICY role_notes ON notes USING (audience = current_role())",
+        params![],
+    ).await?;
+
+    // Without any context nothing matches
+    assert_eq!(visible_note_ids(&rls_conn).await?, Vec::<i64>::new());
+
+    rls_conn.set_user(1);
+    rls_conn.set_context("app.tenant_id", "acme");
+    assert_eq!(visible_note_ids(&rls_conn).await?, vec![1]);
+
+    rls_conn.set_role("admin");
+    assert_eq!(visible_note_ids(&rls_conn).await?, vec![1, 4]);
+
+    rls_conn.set_context("app.tenant_id", "globex");
+    assert_eq!(visible_note_ids(&rls_conn).await?, vec![3]);
+
+    // The functions are also available in regular queries
+    let mut rows = rls_conn.query(
+        "SELECT current_user_id(), current_role(), current_setting('app.tenant_id')",
+        params![],
+    ).await?;
+    let row = rows.next()?.unwrap();
+    assert_eq!(row.get::<i64>(0)?, 1);
+    assert_eq!(row.get::<String>(1)?, "admin");
+    assert_eq!(row.get::<String>(2)?, "globex");
+
+    rls_conn.clear_context();
+    assert_eq!(visible_note_ids(&rls_conn).await?, Vec::<i64>::new());
+
+    Ok(())
+}
+
+async fn visible_note_ids(rls_conn: &RlsConnection) -> Result<Vec<i64>> {
+    let mut rows = rls_conn.query("SELECT id FROM notes ORDER BY id", params![]).await?;
+    let mut ids = Vec::new();
+    while let Some(row) = rows.next()? {
+        ids.push(row.get::<i64>(0)?);
+    }
+    Ok(ids)
+}
+
+#[tokio::test]
+async fn test_policy_placeholders_bind_session_values() -> Result<()> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connect()?;
+
+    conn.execute(
+        "CREATE TABLE users (
+            id INTEGER PRIMARY KEY,
+            username TEXT NOT NULL,
+            tenant_id INTEGER NOT NULL
+        )",
+        params![],
+    ).await?;
+
+    conn.execute(
+        "INSERT INTO users (id, username, tenant_id) VALUES
+        (1, 'alice', 100),
+        (2, 'bob', 100),
+        (3, 'charlie', 200)",
+        params![],
+    ).await?;
+
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+
+    rls_conn.set_admin(true);
+
+    rls_conn.execute(
+        "CREATE POLICY tenant_isolation ON users USING (tenant_id = :tenant_id)",
+        params![],
+    ).await?;
+
+    rls_conn.set_context("tenant_id", 100);
+
+    // Anonymous parameters keep their positions after the policy is added
+    let mut rows = rls_conn.query(
+        "SELECT username FROM users WHERE id > ? AND username <> ? ORDER BY id",
+        params![0, "alice"],
+    ).await?;
+    assert_eq!(rows.next()?.unwrap().get::<String>(0)?, "bob");
+    assert!(rows.next()?.is_none());
+
+    // CASE visits its branches out of textual order, which must not matter
+    let mut rows = rls_conn.query(
+        "SELECT CASE WHEN id = ? THEN ? ELSE ? END FROM users ORDER BY id",
+        params![2, "match", "other"],
+    ).await?;
+    assert_eq!(rows.next()?.unwrap().get::<String>(0)?, "other");
+    assert_eq!(rows.next()?.unwrap().get::<String>(0)?, "match");
+    assert!(rows.next()?.is_none());
+
+    // A caller-supplied :tenant_id cannot override the session value
+    let mut rows = rls_conn.query(
+        "SELECT id FROM users WHERE tenant_id = :tenant_id",
+        named_params! { ":tenant_id": 200 },
+    ).await?;
+    assert!(rows.next()?.is_none());
+
+    // Context values are bound, never spliced into the SQL
+    rls_conn.set_context("tenant_id", "100 OR 1 = 1");
+    let mut rows = rls_conn.query("SELECT id FROM users", params![]).await?;
+    assert!(rows.next()?.is_none());
+
+    // Positional parameters are rejected in policies
+    let result = rls_conn.execute(
+        "CREATE POLICY bad ON users USING (tenant_id = ?)",
+        params![],
+    ).await;
+    assert!(matches!(result, Err(libsql_rls::Error::Policy(_))));
+
+    Ok(())
+}
@@ -0,0 +1,129 @@
+use libsql_rls::{Error, Result, RlsConnection};
+use libsql::{Database, params};
+
+#[tokio::test]
+async fn test_strict_mode_rejects_unchecked_statements() -> Result<()> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connect()?;
+
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+    assert!(rls_conn.is_strict());
+
+    // Known-safe DDL and transaction control still run
+    rls_conn.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, tenant_id INTEGER NOT NULL)", params![]).await?;
+    rls_conn.execute("CREATE INDEX users_tenant ON users (tenant_id)", params![]).await?;
+    rls_conn.execute("ALTER TABLE users ADD COLUMN name TEXT", params![]).await?;
+    rls_conn.execute("BEGIN", params![]).await?;
+    rls_conn.execute("INSERT INTO users (id, tenant_id) VALUES (1, 100), (2, 200)", params![]).await?;
+    rls_conn.execute("COMMIT", params![]).await?;
+    rls_conn.set_admin(true);
+    rls_conn.execute("CREATE POLICY tenant ON users USING (tenant_id = 100)", params![]).await?;
+    rls_conn.set_admin(false);
+
+    for sql in [
+        // Views and triggers run statements that are never rewritten
+        "CREATE VIEW all_users AS SELECT * FROM users",
+        "CREATE TRIGGER copy AFTER INSERT ON users BEGIN SELECT 1; END",
+        "CREATE TABLE copied AS SELECT * FROM users",
+        "SELECT id FROM users; DELETE FROM users",
+        "PRAGMA table_info(users)",
+        // Policies name their table and columns, which these would change
+        "ALTER TABLE users RENAME TO unprotected",
+        "ALTER TABLE users RENAME COLUMN tenant_id TO owner_id",
+        "ALTER TABLE users DROP COLUMN tenant_id",
+    ] {
+        let result = rls_conn.execute(sql, params![]).await;
+        assert!(
+            matches!(result, Err(Error::UnsupportedSql(_))),
+            "expected {sql} to be rejected, got {result:?}"
+        );
+    }
+    assert!(matches!(
+        rls_conn.query("SELECT id FROM users; SELECT 1", params![]).await,
+        Err(Error::UnsupportedSql(_))
+    ));
+
+    // Admin connections pass such statements through unless made strict
+    rls_conn.set_admin(true);
+    assert!(!rls_conn.is_strict());
+    rls_conn.execute("CREATE VIEW all_users AS SELECT * FROM users", params![]).await?;
+    rls_conn.set_strict(true);
+    assert!(matches!(
+        rls_conn.execute("DROP TRIGGER IF EXISTS copy", params![]).await,
+        Err(Error::UnsupportedSql(_))
+    ));
+
+    let mut rows = rls_conn.query("SELECT COUNT(*) FROM users", params![]).await?;
+    assert_eq!(rows.next()?.unwrap().get::<i64>(0)?, 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_strict_mode_runs_the_parsed_statement() -> Result<()> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connect()?;
+
+    conn.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, tenant_id INTEGER NOT NULL)", params![]).await?;
+    conn.execute("INSERT INTO users (id, tenant_id) VALUES (1, 100), (2, 200)", params![]).await?;
+
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+    rls_conn.set_admin(true);
+    rls_conn.execute("CREATE POLICY tenant ON users USING (tenant_id = 100)", params![]).await?;
+    rls_conn.set_admin(false);
+
+    // SQLite doesn't nest comments, so its reading of this is a query of
+    // every user, while the parser only sees `SELECT 1`
+    let mut rows = rls_conn.query("SELECT 1 /* /* */, id FROM users -- */", params![]).await?;
+    assert_eq!(rows.column_count(), 1);
+    assert_eq!(rows.next()?.expect("one row").get::<i64>(0)?, 1);
+    assert!(rows.next()?.is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_ddl_on_protected_tables_requires_admin() -> Result<()> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connect()?;
+
+    conn.execute("CREATE TABLE docs (id INTEGER PRIMARY KEY, tenant_id INTEGER NOT NULL)", params![]).await?;
+    conn.execute("CREATE TABLE memberships (user_id INTEGER NOT NULL, tenant_id INTEGER NOT NULL)", params![]).await?;
+    conn.execute("INSERT INTO docs (id, tenant_id) VALUES (1, 100), (2, 200)", params![]).await?;
+    conn.execute("INSERT INTO memberships (user_id, tenant_id) VALUES (7, 100)", params![]).await?;
+
+    let mut rls_conn = RlsConnection::new_initialized(conn).await?;
+    rls_conn.set_admin(true);
+    rls_conn.execute(
+        "CREATE POLICY member ON docs
+         USING (tenant_id IN (SELECT tenant_id FROM memberships WHERE user_id = current_user_id()))",
+        params![],
+    ).await?;
+    rls_conn.set_admin(false);
+    rls_conn.set_user(7);
+
+    for sql in [
+        // A temporary table would hide memberships from the policy
+        "CREATE TEMP TABLE memberships (user_id INTEGER, tenant_id INTEGER)",
+        "CREATE TABLE temp.memberships (user_id INTEGER, tenant_id INTEGER)",
+        // These would remove or probe the rows of every tenant
+        "DROP TABLE docs",
+        "DROP TABLE IF EXISTS DOCS",
+        "CREATE UNIQUE INDEX docs_tenant ON docs (tenant_id)",
+    ] {
+        let result = rls_conn.execute(sql, params![]).await;
+        assert!(matches!(result, Err(Error::PermissionDenied(_))), "{sql}: {result:?}");
+    }
+
+    let mut rows = rls_conn.query("SELECT id FROM docs", params![]).await?;
+    assert_eq!(rows.next()?.expect("doc 1 is visible").get::<i64>(0)?, 1);
+    assert!(rows.next()?.is_none());
+    drop(rows);
+
+    // Unprotected tables are still the session's to change
+    rls_conn.execute("CREATE INDEX memberships_user ON memberships (user_id)", params![]).await?;
+    rls_conn.set_admin(true);
+    rls_conn.execute("CREATE INDEX docs_tenant ON docs (tenant_id)", params![]).await?;
+
+    Ok(())
+}